@@ -1,13 +1,29 @@
-use std::{
-    cmp::max,
-    fmt::Display,
-    ops::{Index, IndexMut},
+use std::{collections::HashSet, fmt::Display, ops::Index};
+
+use crate::{
+    connect4_eval::EvalConfig,
+    game::{MinimaxGame, WIN},
+    random,
 };
 
-use crate::game::MinimaxGame;
+pub const WIDTH: usize = 7;
+pub const HEIGHT: usize = 6;
+
+/// Each column takes HEIGHT + 1 bits, so the spare bit on top stops shifts wrapping into the
+/// next column
+pub(crate) const COLUMN_BITS: usize = HEIGHT + 1;
+
+/// The bottom space of each column
+pub(crate) const BOTTOM: u64 = bottom_mask();
 
-const WIDTH: usize = 7;
-const HEIGHT: usize = 6;
+/// Every space on the board, leaving out the spare bit on top of each column
+pub(crate) const BOARD: u64 = BOTTOM * ((1 << HEIGHT) - 1);
+
+/// One Zobrist key for each color in each space
+const ZOBRIST_KEYS: [u64; 2 * WIDTH * HEIGHT] = random::zobrist_keys(0xc0ec_74f0);
+
+/// The shifts which move a piece one space up, right, up right and down right
+const DIRECTIONS: [usize; 4] = [1, COLUMN_BITS, COLUMN_BITS + 1, COLUMN_BITS - 1];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Color {
@@ -15,408 +31,1143 @@ pub enum Color {
     Black,
 }
 
-impl Display for Color {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Color {
+    pub fn opponent(&self) -> Color {
         match self {
-            Color::White => write!(f, "x"),
-            Color::Black => write!(f, "o"),
+            Color::White => Color::Black,
+            Color::Black => Color::White,
         }
     }
-}
-
-#[derive(Clone)]
-struct Row([Option<Color>; WIDTH]);
-
-impl Default for Row {
-    fn default() -> Self {
-        Self(Default::default())
-    }
-}
-
-impl Index<usize> for Row {
-    type Output = Option<Color>;
-
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.0[index]
-    }
-}
 
-impl IndexMut<usize> for Row {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.0[index]
+    pub(crate) fn index(&self) -> usize {
+        match self {
+            Color::White => 0,
+            Color::Black => 1,
+        }
     }
 }
 
-impl Display for Row {
+impl Display for Color {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for space in self.0 {
-            let res = match space {
-                Some(s) => write!(f, "{} ", s),
-                None => write!(f, "  "),
-            };
-
-            match res {
-                Err(e) => return Err(e),
-                _ => {}
-            }
+        match self {
+            Color::White => write!(f, "x"),
+            Color::Black => write!(f, "o"),
         }
-
-        Ok(())
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Game {
-    board: [Row; HEIGHT],
+    /// A bitboard of each color's pieces. The space h up from the bottom of column x is
+    /// bit x * (HEIGHT + 1) + h
+    pieces: [u64; 2],
+    /// The number of pieces in each column
+    heights: [usize; WIDTH],
     current_player: Color,
-    last_placement: (usize, usize),
-    current_longest: usize,
-    opponent_longest: usize,
+    moves: usize,
+    hash: u64,
 }
 
-impl<'a> Index<(usize, usize)> for Game {
+impl Index<(usize, usize)> for Game {
     type Output = Option<Color>;
 
+    /// Returns the piece in column x, row y, where row 0 is the top of the board
     fn index(&self, index: (usize, usize)) -> &Self::Output {
-        if index.1 >= self.board.len() || index.0 >= self.board[index.1].0.len() {
-            &None
+        let (x, y) = index;
+        if x >= WIDTH || y >= HEIGHT {
+            return &None;
+        }
+
+        let bit = space(x, HEIGHT - 1 - y);
+        if self.pieces[Color::White.index()] & bit != 0 {
+            &Some(Color::White)
+        } else if self.pieces[Color::Black.index()] & bit != 0 {
+            &Some(Color::Black)
         } else {
-            &self.board[index.1][index.0]
+            &None
         }
     }
 }
 
-impl MinimaxGame<MoveIterator> for Game {
-    fn evaluate(&self, depth: usize) -> i64 {
-        if self.has_won() {
-            return -1000 * depth as i64; // Prefer earlier victories
-        }
+impl MinimaxGame for Game {
+    type Move = usize;
+    type Moves = MoveIterator;
+    type Player = Color;
 
-        let mut score = (self.current_longest * 10) as i64;
+    fn to_move(&self) -> Color {
+        self.current_player
+    }
 
-        // Prefer our tokens in the center and opponent tokens on the edges
-        for row in self.board.iter() {
-            for (column, _) in row.0.iter().enumerate() {
-                let delta = 3 - (column as i64);
-                if row[column] == Some(self.current_player) {
-                    score -= delta.abs();
-                } else if let Some(_) = row[column] {
-                    score += delta.abs();
-                }
-            }
+    fn evaluate(&self, depth: usize) -> i64 {
+        if self.has_won() {
+            return -(WIN + depth as i64); // Prefer earlier victories
         }
 
-        score
+        EvalConfig::DEFAULT.evaluate(self)
     }
 
     fn has_finished(&self) -> bool {
         self.has_won() || self.has_tied()
     }
 
-    fn get_moves(self) -> MoveIterator {
+    fn winner(&self) -> Option<Color> {
+        // Only the player who just moved can have won
+        self.has_won().then_some(self.current_player.opponent())
+    }
+
+    fn legal_moves(&self) -> MoveIterator {
         MoveIterator {
             current: 0,
-            root_game: self,
+            heights: self.heights,
+            finished: self.has_finished(),
         }
     }
+
+    fn apply(&self, mv: usize) -> Self {
+        self.add_piece(mv)
+    }
+
+    fn zobrist_key(&self) -> u64 {
+        self.hash
+    }
+
+    /// Central columns take part in more lines of four, so search them first
+    fn order_moves(&self, moves: &mut [usize]) {
+        moves.sort_by_key(|column| (column.abs_diff(WIDTH / 2), *column));
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Game {
     pub fn new() -> Self {
         Game {
-            board: <[Row; HEIGHT]>::default(),
+            pieces: [0, 0],
+            heights: [0; WIDTH],
             current_player: Color::White,
-            last_placement: (0, 0),
-            current_longest: 0,
-            opponent_longest: 0,
+            moves: 0,
+            hash: 0,
         }
     }
 
+    /// Returns the position after the player to move drops a piece into the column, which must be
+    /// a legal move
     pub fn add_piece(&self, column: usize) -> Self {
         let mut new_board = self.clone();
-        new_board.current_player = match self.current_player {
-            Color::White => Color::Black,
-            Color::Black => Color::White,
-        };
-
-        for i in 0..new_board.board.len() {
-            match new_board[(column, i)] {
-                None => {} // Space is empty, keep going
-                Some(_) => {
-                    new_board.board[i - 1][column] = Some(self.current_player);
-                    new_board.last_placement = (column, i - 1);
-                    new_board.set_longest_chains(column, i - 1);
-                    return new_board;
-                }
-            }
-        }
+        new_board.play(column);
 
-        // Fill the bottom space
-        new_board.board[new_board.board.len() - 1][column] = Some(self.current_player);
-        new_board.last_placement = (column, new_board.board.len() - 1);
-        new_board.set_longest_chains(column, new_board.board.len() - 1);
         new_board
     }
 
-    fn set_longest_chains(&mut self, x: usize, y: usize) {
-        let tmp = self.current_longest;
-        self.current_longest = self.opponent_longest;
-        self.opponent_longest = max(tmp, self.get_longest_chain(x, y));
+    /// The number of pieces played so far
+    pub fn moves(&self) -> usize {
+        self.moves
+    }
+
+    /// Returns a bitboard of the pieces belonging to the player to move and one of every piece
+    pub(crate) fn bitboards(&self) -> (u64, u64) {
+        (
+            self.pieces[self.current_player.index()],
+            self.pieces[0] | self.pieces[1],
+        )
+    }
+
+    /// Returns the position after the player to move drops a piece into the column, or why they
+    /// can't
+    pub fn try_play(&self, column: usize) -> Result<Game, MoveError> {
+        self.check_move(column)?;
+
+        Ok(self.add_piece(column))
+    }
+
+    /// Checks the player to move can drop a piece into the column
+    pub fn check_move(&self, column: usize) -> Result<(), MoveError> {
+        check_move(&self.heights, self.has_finished(), column)
+    }
+
+    /// Drops a piece for the player to move into the column, panicking if it is full. Use
+    /// `try_play` for moves which haven't been checked
+    pub fn play(&mut self, column: usize) {
+        let height = self.heights[column];
+        // A piece above the top would set the spare bit and corrupt the bitboards and hash
+        assert!(height < HEIGHT, "column {} is full", column);
+
+        self.pieces[self.current_player.index()] |= space(column, height);
+        self.hash ^= zobrist_key(self.current_player, column, HEIGHT - 1 - height);
+        self.heights[column] += 1;
+        self.moves += 1;
+        self.current_player = self.current_player.opponent();
+    }
+
+    /// Takes back the last piece played, which must have been in the column
+    pub fn undo(&mut self, column: usize) {
+        self.current_player = self.current_player.opponent();
+        self.moves -= 1;
+        self.heights[column] -= 1;
+
+        let height = self.heights[column];
+        self.pieces[self.current_player.index()] ^= space(column, height);
+        self.hash ^= zobrist_key(self.current_player, column, HEIGHT - 1 - height);
     }
 
     /// Returns true if every space is full
     pub fn has_tied(&self) -> bool {
-        // We can just check the top row as that implies the rest of the column is full
-        !self.board[0].0.contains(&None)
+        self.moves == WIDTH * HEIGHT
     }
 
     /// Returns true if the last piece played won
     pub fn has_won(&self) -> bool {
-        // We only need to check chains from the last space placed
-        self.get_longest_chain(self.last_placement.0, self.last_placement.1) >= 4
-    }
-
-    /// Get the longest chain of tokens matching the color in (x, y)
-    fn get_longest_chain(&self, x: usize, y: usize) -> usize {
-        let ud = self.get_longest_vector(x, y, Direction::Up)
-            + self.get_longest_vector(x, y, Direction::Down)
-            + 1;
-        let lr = self.get_longest_vector(x, y, Direction::Left)
-            + self.get_longest_vector(x, y, Direction::Right)
-            + 1;
-        let urdl = self.get_longest_vector(x, y, Direction::UpRight)
-            + self.get_longest_vector(x, y, Direction::DownLeft)
-            + 1;
-        let uldr = self.get_longest_vector(x, y, Direction::UpLeft)
-            + self.get_longest_vector(x, y, Direction::DownRight)
-            + 1;
-
-        max(ud, max(lr, max(urdl, uldr)))
-    }
-
-    /// Return the longest chain of tokens matching the color in (x, y) in the specified direction
-    fn get_longest_vector(&self, x: usize, y: usize, direction: Direction) -> usize {
-        let color = self[(x, y)].expect("no token in space");
-
-        let mut count = 0;
-        let (mut new_x, mut new_y) = direction.calculate_new_indices(x, y);
-
-        while let Some(inner) = self[(new_x, new_y)] {
-            if inner != color {
-                break;
+        // Only the player who just moved can have made a new line
+        has_four(self.pieces[self.current_player.opponent().index()])
+    }
+
+    /// The longest line of pieces belonging to the player to move
+    pub fn current_longest(&self) -> usize {
+        longest_chain(self.pieces[self.current_player.index()])
+    }
+
+    /// The longest line of pieces belonging to the player who just moved
+    pub fn opponent_longest(&self) -> usize {
+        longest_chain(self.pieces[self.current_player.opponent().index()])
+    }
+}
+
+/// Why a piece can't be dropped into a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    /// There is no column with this number
+    OutOfRange(usize),
+    ColumnFull(usize),
+    /// One of the players has already won, or the board is full
+    GameOver,
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::OutOfRange(column) => write!(f, "column {} is off the board", column),
+            MoveError::ColumnFull(column) => write!(f, "column {} is full", column),
+            MoveError::GameOver => write!(f, "the game is already over"),
+        }
+    }
+}
+
+/// Why a position couldn't be read from its notation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotationError {
+    /// A character which isn't part of the notation
+    InvalidCharacter(char),
+    /// A move into a column which is already full, numbered from 1
+    ColumnFull(usize),
+    /// A move after the game had already been won
+    GameOver,
+    /// The board doesn't have HEIGHT rows of WIDTH spaces, or the side to move is missing
+    WrongShape,
+    /// A piece with an empty space below it, in the column numbered from 1
+    FloatingPiece(usize),
+    /// The piece counts or side to move couldn't come from players taking turns, starting with x
+    WrongTurn,
+    /// The player to move already has four in a row, so the game should have ended before their
+    /// opponent's last move
+    WonOutOfTurn,
+}
+
+impl Display for NotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotationError::InvalidCharacter(c) => write!(f, "unexpected character '{}'", c),
+            NotationError::ColumnFull(column) => write!(f, "column {} is full", column),
+            NotationError::GameOver => write!(f, "the game is already over"),
+            NotationError::WrongShape => {
+                write!(
+                    f,
+                    "expected {} rows of {} spaces and a side to move",
+                    HEIGHT, WIDTH
+                )
+            }
+            NotationError::FloatingPiece(column) => {
+                write!(f, "column {} has a piece above an empty space", column)
+            }
+            NotationError::WrongTurn => write!(f, "the players didn't take turns"),
+            NotationError::WonOutOfTurn => write!(f, "the player to move has already won"),
+        }
+    }
+}
+
+impl Game {
+    /// Plays a sequence of columns numbered from 1, such as "4453"
+    pub fn from_moves(moves: &str) -> Result<Game, NotationError> {
+        let game = Game::new();
+        let columns = game.parse_moves(moves)?;
+
+        Ok(columns
+            .into_iter()
+            .fold(game, |game, column| game.add_piece(column)))
+    }
+
+    /// Reads a sequence of columns numbered from 1, checking that each can be played in turn from
+    /// this position
+    pub fn parse_moves(&self, moves: &str) -> Result<Vec<usize>, NotationError> {
+        let mut game = self.clone();
+        let mut columns = vec![];
+
+        for c in moves.chars() {
+            let column = match c.to_digit(10) {
+                Some(digit) if (1..=WIDTH as u32).contains(&digit) => digit as usize - 1,
+                _ => return Err(NotationError::InvalidCharacter(c)),
+            };
+
+            if game.has_won() {
+                return Err(NotationError::GameOver);
+            }
+            if game.heights[column] == HEIGHT {
+                return Err(NotationError::ColumnFull(column + 1));
             }
 
-            let new = direction.calculate_new_indices(new_x, new_y);
-            new_x = new.0;
-            new_y = new.1;
+            game.play(column);
+            columns.push(column);
+        }
 
-            count += 1;
+        Ok(columns)
+    }
+
+    /// Returns a sequence of columns numbered from 1 which reaches this position, or None if no
+    /// game could reach it. Only the position is stored, so this searches backwards by taking
+    /// pieces off the top of the columns
+    pub fn to_moves(&self) -> Option<String> {
+        // Whoever is to move can't already have won
+        if has_four(self.pieces[self.current_player.index()]) {
+            return None;
         }
 
-        count
+        let mut game = self.clone();
+        let mut moves = vec![];
+        let mut dead_ends = HashSet::new();
+
+        if !game.unplay(&mut moves, &mut dead_ends) {
+            return None;
+        }
+
+        Some(
+            moves
+                .iter()
+                .map(|column| char::from_digit(*column as u32 + 1, 10).expect("columns are digits"))
+                .collect(),
+        )
     }
+
+    /// Takes pieces back until the board is empty, then pushes the columns in the order they were
+    /// played. Positions which can't be unwound are remembered by hash, so they are only tried once
+    fn unplay(&mut self, moves: &mut Vec<usize>, dead_ends: &mut HashSet<u64>) -> bool {
+        if self.moves == 0 {
+            return true;
+        }
+
+        if dead_ends.contains(&self.hash) {
+            return false;
+        }
+
+        let last_player = self.current_player.opponent().index();
+        for column in 0..WIDTH {
+            if self.heights[column] == 0
+                || self.pieces[last_player] & space(column, self.heights[column] - 1) == 0
+            {
+                continue;
+            }
+
+            self.undo(column);
+
+            // The game would have stopped at a position where someone had already won
+            let finished = self.pieces.iter().any(|pieces| has_four(*pieces));
+            if !finished && self.unplay(moves, dead_ends) {
+                moves.push(column);
+                return true;
+            }
+
+            self.play(column);
+        }
+
+        dead_ends.insert(self.hash);
+        false
+    }
+
+    /// Reads a board written row by row from the top, separated by '/'. Each row lists x and o
+    /// pieces, with digits for runs of empty spaces, and the board is followed by the side to move.
+    /// The empty board is "7/7/7/7/7/7 x"
+    pub fn from_fen(fen: &str) -> Result<Game, NotationError> {
+        let mut parts = fen.split_whitespace();
+        let (Some(board), Some(side), None) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(NotationError::WrongShape);
+        };
+
+        let rows: Vec<&str> = board.split('/').collect();
+        if rows.len() != HEIGHT {
+            return Err(NotationError::WrongShape);
+        }
+
+        let mut game = Game::new();
+        for (y, row) in rows.iter().enumerate() {
+            let mut x = 0;
+
+            for c in row.chars() {
+                let color = match c {
+                    'x' => Color::White,
+                    'o' => Color::Black,
+                    '1'..='9' => {
+                        x += c.to_digit(10).expect("checked it is a digit") as usize;
+                        continue;
+                    }
+                    _ => return Err(NotationError::InvalidCharacter(c)),
+                };
+
+                if x >= WIDTH {
+                    return Err(NotationError::WrongShape);
+                }
+
+                let height = HEIGHT - 1 - y;
+                game.pieces[color.index()] |= space(x, height);
+                game.hash ^= zobrist_key(color, x, y);
+                game.heights[x] = game.heights[x].max(height + 1);
+                game.moves += 1;
+                x += 1;
+            }
+
+            if x != WIDTH {
+                return Err(NotationError::WrongShape);
+            }
+        }
+
+        for column in 0..WIDTH {
+            let filled = (game.pieces[0] | game.pieces[1]) >> (column * COLUMN_BITS);
+            if (filled & ((1 << HEIGHT) - 1)).count_ones() as usize != game.heights[column] {
+                return Err(NotationError::FloatingPiece(column + 1));
+            }
+        }
+
+        let side = match side {
+            "x" => Color::White,
+            "o" => Color::Black,
+            _ => return Err(NotationError::WrongShape),
+        };
+
+        let white = game.pieces[Color::White.index()].count_ones();
+        let black = game.pieces[Color::Black.index()].count_ones();
+        let expected = if white == black {
+            Color::White
+        } else if white == black + 1 {
+            Color::Black
+        } else {
+            return Err(NotationError::WrongTurn);
+        };
+
+        if side != expected {
+            return Err(NotationError::WrongTurn);
+        }
+        if has_four(game.pieces[side.index()]) {
+            return Err(NotationError::WonOutOfTurn);
+        }
+        game.current_player = side;
+
+        Ok(game)
+    }
+
+    /// Writes the board in the notation read by `from_fen`
+    pub fn to_fen(&self) -> String {
+        let mut rows = vec![];
+
+        for y in 0..HEIGHT {
+            let mut row = String::new();
+            let mut empty = 0;
+
+            for x in 0..WIDTH {
+                match self[(x, y)] {
+                    None => empty += 1,
+                    Some(color) => {
+                        if empty > 0 {
+                            row += &empty.to_string();
+                            empty = 0;
+                        }
+                        row += &color.to_string();
+                    }
+                }
+            }
+
+            if empty > 0 {
+                row += &empty.to_string();
+            }
+            rows.push(row);
+        }
+
+        format!("{} {}", rows.join("/"), self.current_player)
+    }
+}
+
+/// Returns true if there are four in a row anywhere on a bitboard
+fn has_four(pieces: u64) -> bool {
+    DIRECTIONS.iter().any(|shift| {
+        let pairs = pieces & (pieces >> shift);
+        pairs & (pairs >> (2 * shift)) != 0
+    })
+}
+
+/// Finds the empty spaces which would complete a line for the pieces
+pub(crate) fn winning_spaces(pieces: u64, mask: u64) -> u64 {
+    // Vertical lines can only be completed on top
+    let mut spaces = (pieces << 1) & (pieces << 2) & (pieces << 3);
+
+    // The other directions could be completed at any of the four spaces
+    for shift in [COLUMN_BITS, COLUMN_BITS - 1, COLUMN_BITS + 1] {
+        let pairs = (pieces << shift) & (pieces << (2 * shift));
+        spaces |= pairs & (pieces << (3 * shift));
+        spaces |= pairs & (pieces >> shift);
+
+        let pairs = (pieces >> shift) & (pieces >> (2 * shift));
+        spaces |= pairs & (pieces << shift);
+        spaces |= pairs & (pieces >> (3 * shift));
+    }
+
+    spaces & (BOARD ^ mask)
+}
+
+/// Returns the longest line in any direction on a bitboard
+fn longest_chain(pieces: u64) -> usize {
+    DIRECTIONS
+        .iter()
+        .map(|shift| {
+            // Each step keeps only the pieces which start a line one longer
+            let mut chain = 0;
+            let mut remaining = pieces;
+            while remaining != 0 {
+                chain += 1;
+                remaining &= remaining >> shift;
+            }
+
+            chain
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+const fn bottom_mask() -> u64 {
+    let mut mask = 0;
+    let mut column = 0;
+    while column < WIDTH {
+        mask |= 1 << (column * COLUMN_BITS);
+        column += 1;
+    }
+
+    mask
+}
+
+/// The bit for the space `height` up from the bottom of the column
+fn space(column: usize, height: usize) -> u64 {
+    1 << (column * COLUMN_BITS + height)
+}
+
+fn zobrist_key(color: Color, x: usize, y: usize) -> u64 {
+    let offset = match color {
+        Color::White => 0,
+        Color::Black => WIDTH * HEIGHT,
+    };
+
+    ZOBRIST_KEYS[offset + y * WIDTH + x]
 }
 
 impl Display for Game {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Write the board header
-        let mut res = write!(f, "  0 1 2 3 4 5 6\n");
-
-        for (i, line) in self.board.iter().enumerate() {
-            res = res.and_then(|_| write!(f, "{} {}\n", i, line));
+        writeln!(f, "  0 1 2 3 4 5 6")?;
+
+        for y in 0..HEIGHT {
+            write!(f, "{} ", y)?;
+            for x in 0..WIDTH {
+                match self[(x, y)] {
+                    Some(color) => write!(f, "{} ", color)?,
+                    None => write!(f, "  ")?,
+                }
+            }
+            writeln!(f)?;
         }
 
-        res
+        Ok(())
+    }
+}
+
+/// The check shared by `Game::try_play` and `MoveIterator`
+fn check_move(heights: &[usize; WIDTH], finished: bool, column: usize) -> Result<(), MoveError> {
+    if column >= WIDTH {
+        Err(MoveError::OutOfRange(column))
+    } else if finished {
+        Err(MoveError::GameOver)
+    } else if heights[column] == HEIGHT {
+        Err(MoveError::ColumnFull(column))
+    } else {
+        Ok(())
     }
 }
 
+/// The columns which can be played, in order. There are none once the game is over
 pub struct MoveIterator {
-    root_game: Game,
+    heights: [usize; WIDTH],
+    finished: bool,
     current: usize,
 }
 
 impl Iterator for MoveIterator {
-    type Item = (usize, Game);
+    type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Skip full columns
-        while let Some(_) = self.root_game[(self.current, 0)] {
+        while self.current < WIDTH {
             self.current += 1;
+
+            if check_move(&self.heights, self.finished, self.current - 1).is_ok() {
+                return Some(self.current - 1);
+            }
         }
 
-        if self.current >= WIDTH {
-            None
-        } else {
-            let game = self.root_game.add_piece(self.current);
-            self.current += 1;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        game::{
+            alpha_beta, alpha_beta_tt,
+            mcts::{Mcts, MctsOptions},
+            minimax,
+            ordering::OrderingOptions,
+            search::{iterative_deepening, Algorithm, SearchLimits, Searcher},
+            transposition::TranspositionTable,
+            MinimaxGame,
+        },
+        random::Rng,
+    };
+
+    use super::{longest_chain, space, Color, Game, MoveError, NotationError, HEIGHT, WIDTH};
 
-            Some((self.current - 1, game))
+    #[test]
+    pub fn longest_chains_set_correctly() {
+        let game = Game::from_moves("12122135354346463251").unwrap();
+
+        assert_eq!(game.current_longest(), 3);
+        assert_eq!(game.opponent_longest(), 2);
+    }
+
+    #[test]
+    pub fn longest_chain_on_bitboard() {
+        assert_eq!(longest_chain(0), 0);
+        assert_eq!(longest_chain(space(3, 2)), 1);
+
+        // Vertical, horizontal and both diagonals
+        assert_eq!(longest_chain(space(0, 0) | space(0, 1) | space(0, 2)), 3);
+        assert_eq!(longest_chain(space(1, 0) | space(2, 0) | space(4, 0)), 2);
+        assert_eq!(longest_chain(space(1, 1) | space(2, 2) | space(3, 3)), 3);
+        assert_eq!(longest_chain(space(1, 3) | space(2, 2)), 2);
+
+        // Lines don't wrap from the top of one column to the bottom of the next
+        assert_eq!(longest_chain(space(0, HEIGHT - 1) | space(1, 0)), 1);
+    }
+
+    #[test]
+    pub fn get_longest_chain() {
+        let game = Game::from_moves("12122135354345443251").unwrap();
+
+        assert_eq!(game.current_longest(), 3);
+        assert_eq!(game.opponent_longest(), 3);
+    }
+
+    #[test]
+    pub fn has_won() {
+        let game = Game::new();
+        assert!(!game.has_won());
+
+        for moves in [
+            // Vertical
+            "1212121",
+            // Horizontal
+            "1122334",
+            // Up and to the right
+            "12233434474",
+            // Down and to the right
+            "76655454414",
+        ] {
+            let game = Game::from_moves(&moves[..moves.len() - 1]).unwrap();
+            assert!(!game.has_won(), "{}", moves);
+
+            let game = Game::from_moves(moves).unwrap();
+            assert!(game.has_won(), "{}", moves);
+            assert_eq!(game.winner(), Some(Color::White));
         }
     }
-}
 
-enum Direction {
-    Up,
-    UpLeft,
-    Left,
-    DownLeft,
-    Down,
-    DownRight,
-    Right,
-    UpRight,
-}
+    #[test]
+    pub fn move_iterator() {
+        //   0 1 2 3 4 5 6
+        // 3 o o x
+        // 4 o o o x
+        // 5 x x x o x
+        let game = Game::from_moves("142133514232").unwrap();
+        assert_eq!(game.to_move(), Color::White);
+        assert_eq!(game.legal_moves().count(), WIDTH);
+
+        for new_move in game.legal_moves() {
+            let game = game.apply(new_move);
+            assert_eq!(new_move == 1, game.has_won());
 
-impl Direction {
-    fn calculate_new_indices(&self, x: usize, y: usize) -> (usize, usize) {
-        match self {
-            Direction::Up => (x, y.wrapping_sub(1)),
-            Direction::UpLeft => (x.wrapping_sub(1), y.wrapping_sub(1)),
-            Direction::Left => (x.wrapping_sub(1), y),
-            Direction::DownLeft => (x.wrapping_sub(1), y + 1),
-            Direction::Down => (x, y + 1),
-            Direction::DownRight => (x + 1, y + 1),
-            Direction::Right => (x + 1, y),
-            Direction::UpRight => (x + 1, y.wrapping_sub(1)),
+            let y = match new_move {
+                0..=2 => 2,
+                3 => 3,
+                4 => 4,
+                _ => 5,
+            };
+            assert_eq!(game[(new_move, y)], Some(Color::White));
+
+            // Nothing can be played after a win
+            if new_move == 1 {
+                assert_eq!(game.legal_moves().count(), 0);
+            }
+
+            // Go one level deeper to test black win finding
+            if new_move > 1 {
+                for new_move in game.legal_moves() {
+                    assert_eq!(new_move == 0, game.apply(new_move).has_won());
+                }
+            }
         }
+
+        // Full columns are skipped
+        let game = Game::from_moves("111111").unwrap();
+        assert_eq!(
+            game.legal_moves().collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5, 6]
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::{connect4::Direction, game::MinimaxGame};
+    #[test]
+    pub fn undo_restores_the_game() {
+        let mut game = Game::from_moves("4435").unwrap();
+        let before = game.clone();
 
-    use super::{Color, Game, Row};
+        for column in [1, 3, 5] {
+            game.play(column);
+        }
+        assert_ne!(game, before);
+
+        for column in [5, 3, 1] {
+            game.undo(column);
+        }
+        assert_eq!(game, before);
+        assert_eq!(game.zobrist_key(), before.zobrist_key());
+    }
 
     #[test]
-    pub fn longest_chains_set_correctly() {
-        let game = Game::new()
-            .add_piece(0)
-            .add_piece(1)
-            .add_piece(0)
-            .add_piece(1)
-            .add_piece(1)
-            .add_piece(0)
-            .add_piece(2)
-            .add_piece(4)
-            .add_piece(2)
-            .add_piece(4)
-            .add_piece(3)
-            .add_piece(2)
-            .add_piece(3)
-            .add_piece(5)
-            .add_piece(3)
-            .add_piece(5)
-            .add_piece(2)
-            .add_piece(1)
-            .add_piece(4)
-            .add_piece(0);
-
-        assert_eq!(game.current_longest, 3);
-        assert_eq!(game.opponent_longest, 2);
+    pub fn display() {
+        let game = Game::from_moves("443").unwrap();
+
+        assert_eq!(
+            format!("{}", game),
+            "  0 1 2 3 4 5 6\n\
+             0               \n\
+             1               \n\
+             2               \n\
+             3               \n\
+             4       o       \n\
+             5     x x       \n"
+        );
     }
 
     #[test]
-    pub fn get_longest_vector() {
-        let game = Game::new()
-            .add_piece(0)
-            .add_piece(1)
-            .add_piece(0)
-            .add_piece(1)
-            .add_piece(0)
-            .add_piece(1)
-            .add_piece(0);
+    pub fn search_finds_winning_move() {
+        let game = Game::from_moves("112233").unwrap();
 
-        let longest = game.get_longest_vector(0, 5, Direction::Up);
-        assert_eq!(3, longest);
+        assert_eq!(game.to_move(), Color::White);
+        assert_eq!(minimax(&game, 3), 3);
+        assert_eq!(alpha_beta(&game, 3).best_move, 3);
 
-        let longest = game.get_longest_vector(1, 3, Direction::Down);
-        assert_eq!(2, longest);
+        // Black must block
+        let game = game.add_piece(6);
+        assert_eq!(minimax(&game, 3), 3);
+        assert_eq!(alpha_beta(&game, 3).best_move, 3);
     }
 
     #[test]
-    pub fn get_longest_chain() {
-        let game = Game::new()
-            .add_piece(0)
-            .add_piece(1)
-            .add_piece(0)
-            .add_piece(1)
-            .add_piece(1)
-            .add_piece(0)
-            .add_piece(2)
-            .add_piece(4)
-            .add_piece(2)
-            .add_piece(4)
-            .add_piece(3)
-            .add_piece(2)
-            .add_piece(3)
-            .add_piece(4)
-            .add_piece(3)
-            .add_piece(3)
-            .add_piece(2)
-            .add_piece(1)
-            .add_piece(4)
-            .add_piece(0);
-
-        let longest = game.get_longest_chain(3, 3);
-        assert_eq!(3, longest);
-
-        let longest = game.get_longest_chain(1, 2);
-        assert_eq!(2, longest);
+    pub fn iterative_deepening_matches_alpha_beta() {
+        let game = Game::from_moves("443").unwrap();
+
+        assert_eq!(
+            iterative_deepening(&game, SearchLimits::depth(5)).best_move,
+            alpha_beta(&game, 5).best_move
+        );
     }
 
     #[test]
-    #[rustfmt::skip]
-    pub fn has_won() {
-        let mut game = Game::new();
+    pub fn iterative_deepening_respects_budgets() {
+        let game = Game::from_moves("112233").unwrap();
+
+        // The first iteration always completes, so even a tiny budget finds the immediate win
+        assert_eq!(
+            iterative_deepening(&game, SearchLimits::nodes(1)).best_move,
+            3
+        );
+
+        let start = Instant::now();
+        assert_eq!(
+            iterative_deepening(&game, SearchLimits::time(Duration::from_millis(50))).best_move,
+            3
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    pub fn algorithms_agree() {
+        let game = Game::from_moves("4435").unwrap();
+        let expected = Searcher::new(Algorithm::Minimax).search(&game, 5);
+
+        for algorithm in [
+            Algorithm::AlphaBeta,
+            Algorithm::FailSoft,
+            Algorithm::Negascout,
+            Algorithm::MtdF,
+        ] {
+            let result = Searcher::new(algorithm).search(&game, 5);
+
+            assert_eq!(result.score, expected.score, "{:?}", algorithm);
+            assert!(result.nodes < expected.nodes, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    pub fn move_ordering_reduces_nodes() {
+        let game = Game::from_moves("434").unwrap();
+
+        let unordered =
+            Searcher::new(Algorithm::AlphaBeta).iterative_deepening(&game, SearchLimits::depth(7));
+        let ordered = Searcher::new(Algorithm::AlphaBeta)
+            .with_ordering(OrderingOptions::all())
+            .iterative_deepening(&game, SearchLimits::depth(7));
+
+        assert_eq!(ordered.score, unordered.score);
+        assert!(ordered.nodes < unordered.nodes);
+
+        let mut table = TranspositionTable::new(1 << 16);
+        let with_table = Searcher::new(Algorithm::AlphaBeta)
+            .with_table(&mut table)
+            .with_ordering(OrderingOptions::all())
+            .iterative_deepening(&game, SearchLimits::depth(7));
+
+        assert_eq!(with_table.score, unordered.score);
+        assert!(with_table.nodes < ordered.nodes);
+    }
 
-        game.board = [Row([None; 7]), 
-            Row([Some(Color::Black), Some(Color::White), Some(Color::Black), None, None, None, None]),
-            Row([Some(Color::Black), Some(Color::White), Some(Color::Black), None, None, None, None]),
-            Row([Some(Color::Black), Some(Color::Black), Some(Color::White), None, None, None, None]),
-            Row([Some(Color::Black), Some(Color::Black), Some(Color::Black), Some(Color::White), None, None, None]),
-            Row([Some(Color::White), Some(Color::White), Some(Color::White), Some(Color::Black), Some(Color::White), None, None])];
+    #[test]
+    pub fn order_moves_prefers_centre() {
+        let mut moves: Vec<usize> = Game::new().legal_moves().collect();
+        Game::new().order_moves(&mut moves);
 
-        game.last_placement = (0, 3);
-        assert!(game.has_won());
+        assert_eq!(moves, vec![3, 2, 4, 1, 5, 0, 6]);
+    }
 
-        game.last_placement = (2, 3);
-        assert!(game.has_won());
+    fn three_in_a_row() -> Game {
+        Game::from_moves("112233").unwrap()
+    }
 
-        game.last_placement = (1, 4);
-        assert!(!game.has_won());
+    #[test]
+    pub fn mcts_finds_winning_move() {
+        let mut mcts = Mcts::new(three_in_a_row(), MctsOptions::default());
+
+        assert_eq!(mcts.search(), 3);
+
+        let statistics = mcts.root_statistics();
+        assert_eq!(statistics.iter().map(|s| s.visits).sum::<u64>(), 1000);
+        assert_eq!(statistics[3].win_rate(), 1.0);
     }
 
     #[test]
-    #[rustfmt::skip]
-    pub fn move_iterator() {
-        let mut game = Game::new();
+    pub fn mcts_blocks_losing_move() {
+        let mut mcts = Mcts::new(three_in_a_row().add_piece(6), MctsOptions::default());
 
-        game.board = [Row([None; 7]), 
-            Row([None; 7]),
-            Row([None; 7]),
-            Row([Some(Color::Black), Some(Color::Black), Some(Color::White), None, None, None, None]),
-            Row([Some(Color::Black), Some(Color::Black), Some(Color::Black), Some(Color::White), None, None, None]),
-            Row([Some(Color::White), Some(Color::White), Some(Color::White), Some(Color::Black), Some(Color::White), None, None])];
+        assert_eq!(mcts.search(), 3);
+    }
 
-        for (new_move, game) in game.get_moves() {
-            assert_eq!(new_move == 1, game.has_won());
+    #[test]
+    pub fn mcts_reuses_tree() {
+        let mut mcts = Mcts::new(Game::new(), MctsOptions::default());
+        let best = mcts.search();
+        let size = mcts.size();
+
+        mcts.advance(best);
+
+        let visits: u64 = mcts.root_statistics().iter().map(|s| s.visits).sum();
+        assert!(visits > 0);
+        assert!(mcts.size() > 1 && mcts.size() < size);
+
+        // Opponent replies are also kept
+        mcts.advance(3);
+        assert!(mcts.size() > 1);
+    }
+
+    #[test]
+    pub fn mcts_root_parallel() {
+        let options = MctsOptions {
+            threads: 4,
+            iterations: Some(250),
+            ..MctsOptions::default()
+        };
+        let mut mcts = Mcts::new(three_in_a_row(), options);
 
-            if new_move < 3 {
-                assert_eq!(game.last_placement, (new_move, 2));
-            } else if new_move == 3 {
-                assert_eq!(game.last_placement, (new_move, 3));
-            } else if new_move == 4 {
-                assert_eq!(game.last_placement, (new_move, 4));
-            } else {
-                assert_eq!(game.last_placement, (new_move, 5));
+        assert_eq!(mcts.search(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "iteration or time limit")]
+    pub fn mcts_needs_a_limit() {
+        let options = MctsOptions {
+            iterations: None,
+            time: None,
+            ..MctsOptions::default()
+        };
+        Mcts::new(Game::new(), options);
+    }
+
+    #[test]
+    pub fn mcts_beats_random_player() {
+        let mut rng = Rng::new(11);
+
+        for mcts_color in [Color::White, Color::Black] {
+            let mut game = Game::new();
+
+            while !game.has_finished() {
+                let mv = if game.to_move() == mcts_color {
+                    Mcts::new(game.clone(), MctsOptions::default()).search()
+                } else {
+                    let moves: Vec<usize> = game.legal_moves().collect();
+                    moves[rng.below(moves.len())]
+                };
+
+                game = game.apply(mv);
             }
 
-            // Go one level deeper to test black win finding
-            if new_move == 1 {
-                for (new_move, game) in game.get_moves() {
-                    assert_eq!(new_move == 0, game.has_won());
+            assert_eq!(game.winner(), Some(mcts_color));
+        }
+    }
+
+    #[test]
+    pub fn zobrist_key_is_order_independent() {
+        let game = Game::from_moves("1234").unwrap();
+        let transposed = Game::from_moves("3412").unwrap();
+        let different = Game::from_moves("2134").unwrap();
+
+        assert_eq!(game.zobrist_key(), transposed.zobrist_key());
+        assert_ne!(game.zobrist_key(), different.zobrist_key());
+        assert_ne!(game.zobrist_key(), Game::new().zobrist_key());
+    }
+
+    #[test]
+    pub fn alpha_beta_tt_matches_alpha_beta() {
+        let mut game = Game::from_moves("443").unwrap();
+        let mut table = TranspositionTable::new(1 << 16);
+
+        for _ in 0..4 {
+            let expected = alpha_beta(&game, 5).best_move;
+            assert_eq!(alpha_beta_tt(&game, 5, &mut table), expected);
+
+            game = game.add_piece(expected);
+        }
+
+        assert!(table.hits() > 0);
+    }
+
+    #[test]
+    pub fn transposition_table_visits_fewer_nodes() {
+        for moves in ["", "443", "4453"] {
+            let game = Game::from_moves(moves).unwrap();
+            let plain = alpha_beta(&game, 6);
+
+            let mut table = TranspositionTable::new(1 << 16);
+            let cached = Searcher::new(Algorithm::AlphaBeta)
+                .with_table(&mut table)
+                .search(&game, 6);
+
+            assert_eq!(cached.score, plain.score, "{}", moves);
+            assert!(cached.nodes < plain.nodes, "{}", moves);
+        }
+    }
+
+    #[test]
+    pub fn from_moves() {
+        let game = Game::from_moves("4453").unwrap();
+        assert_eq!(
+            game,
+            Game::new()
+                .add_piece(3)
+                .add_piece(3)
+                .add_piece(4)
+                .add_piece(2)
+        );
+
+        assert_eq!(Game::from_moves(""), Ok(Game::new()));
+        assert_eq!(
+            Game::from_moves("48"),
+            Err(NotationError::InvalidCharacter('8'))
+        );
+        assert_eq!(
+            Game::from_moves("40"),
+            Err(NotationError::InvalidCharacter('0'))
+        );
+        assert_eq!(
+            Game::from_moves("4 4"),
+            Err(NotationError::InvalidCharacter(' '))
+        );
+        assert_eq!(
+            Game::from_moves("4444444"),
+            Err(NotationError::ColumnFull(4))
+        );
+        assert_eq!(Game::from_moves("12121212"), Err(NotationError::GameOver));
+    }
+
+    #[test]
+    pub fn parse_moves() {
+        let game = Game::from_moves("4444").unwrap();
+
+        assert_eq!(game.parse_moves("453"), Ok(vec![3, 4, 2]));
+        assert_eq!(game.parse_moves("444"), Err(NotationError::ColumnFull(4)));
+        assert_eq!(
+            game.parse_moves("4x"),
+            Err(NotationError::InvalidCharacter('x'))
+        );
+    }
+
+    #[test]
+    pub fn to_moves_round_trips() {
+        let mut rng = Rng::new(8);
+
+        for _ in 0..50 {
+            let mut game = Game::new();
+            while !game.has_finished() {
+                let moves: Vec<usize> = game.legal_moves().collect();
+                game = game.apply(moves[rng.below(moves.len())]);
+
+                let notation = game.to_moves().expect("reachable position");
+                assert_eq!(notation.len(), game.moves);
+                assert_eq!(Game::from_moves(&notation), Ok(game.clone()));
+            }
+        }
+    }
+
+    #[test]
+    pub fn to_moves_rejects_unreachable_positions() {
+        // x has two separate fours, which no single last move could have completed
+        let game = Game::from_fen("7/7/7/o6/oooxxxx/xxxxooo o").unwrap();
+        assert_eq!(game.to_moves(), None);
+    }
+
+    #[test]
+    pub fn fen() {
+        assert_eq!(Game::new().to_fen(), "7/7/7/7/7/7 x");
+        assert_eq!(Game::from_fen("7/7/7/7/7/7 x"), Ok(Game::new()));
+
+        let game = Game::from_moves("4453").unwrap();
+        assert_eq!(game.to_fen(), "7/7/7/7/3o3/2oxx2 x");
+        assert_eq!(Game::from_fen(&game.to_fen()), Ok(game.clone()));
+        assert_eq!(game.to_moves().map(|moves| moves.len()), Some(4));
+
+        let game = game.add_piece(0);
+        assert_eq!(game.to_fen(), "7/7/7/7/3o3/x1oxx2 o");
+        assert_eq!(Game::from_fen(&game.to_fen()), Ok(game));
+    }
+
+    #[test]
+    pub fn fen_errors() {
+        for (fen, error) in [
+            ("7/7/7/7/7 x", NotationError::WrongShape),
+            ("7/7/7/7/7/7", NotationError::WrongShape),
+            ("7/7/7/7/7/6 x", NotationError::WrongShape),
+            ("7/7/7/7/7/8 x", NotationError::WrongShape),
+            ("7/7/7/7/7/7 z", NotationError::WrongShape),
+            ("7/7/7/7/7/3y3 x", NotationError::InvalidCharacter('y')),
+            ("7/7/7/7/3x3/7 o", NotationError::FloatingPiece(4)),
+            ("7/7/7/7/7/3x3 x", NotationError::WrongTurn),
+            ("7/7/7/7/7/2xx3 o", NotationError::WrongTurn),
+            ("7/7/7/7/7/3o3 x", NotationError::WrongTurn),
+            ("7/7/7/o6/o6/xxxxoo1 x", NotationError::WonOutOfTurn),
+            ("7/7/oooo3/xxxx3/oooo3/xxxx3 x", NotationError::WonOutOfTurn),
+        ] {
+            assert_eq!(Game::from_fen(fen), Err(error), "{}", fen);
+        }
+    }
+
+    #[test]
+    pub fn try_play() {
+        let game = Game::from_moves("111111").unwrap();
+
+        assert_eq!(game.try_play(0), Err(MoveError::ColumnFull(0)));
+        assert_eq!(game.try_play(WIDTH), Err(MoveError::OutOfRange(WIDTH)));
+        assert_eq!(game.try_play(1), Ok(game.add_piece(1)));
+
+        let won = Game::from_moves("1212121").unwrap();
+        assert_eq!(won.try_play(3), Err(MoveError::GameOver));
+        assert_eq!(
+            won.try_play(WIDTH + 1),
+            Err(MoveError::OutOfRange(WIDTH + 1))
+        );
+
+        assert_eq!(MoveError::ColumnFull(0).to_string(), "column 0 is full");
+    }
+
+    #[test]
+    #[should_panic(expected = "column 0 is full")]
+    pub fn full_column() {
+        Game::from_moves("111111").unwrap().add_piece(0);
+    }
+
+    #[test]
+    pub fn random_games_never_panic() {
+        let mut rng = Rng::new(47);
+
+        for _ in 0..500 {
+            let mut game = Game::new();
+            let mut columns = vec![];
+
+            loop {
+                let legal: Vec<usize> = game.legal_moves().collect();
+                assert_eq!(legal.is_empty(), game.has_finished());
+
+                // Try columns off the board too, which should be rejected rather than panic
+                let column = rng.below(WIDTH + 2);
+                match game.try_play(column) {
+                    Ok(next) => {
+                        assert!(legal.contains(&column));
+                        assert_eq!(next.moves(), game.moves() + 1);
+                        columns.push(column);
+                        game = next;
+                    }
+                    Err(MoveError::OutOfRange(_)) => assert!(column >= WIDTH),
+                    Err(MoveError::ColumnFull(full)) => {
+                        assert_eq!(full, column);
+                        assert!(!legal.contains(&column));
+                        assert!(game[(column, 0)].is_some());
+                    }
+                    Err(MoveError::GameOver) => {
+                        assert!(game.has_finished());
+                        break;
+                    }
                 }
             }
+
+            // The moves played can be read back and taken back
+            let moves: String = columns.iter().map(|c| (b'1' + *c as u8) as char).collect();
+            assert_eq!(Game::from_moves(&moves).as_ref(), Ok(&game));
+            for column in columns.iter().rev() {
+                game.undo(*column);
+            }
+            assert_eq!(game, Game::new());
         }
     }
 }
@@ -0,0 +1,254 @@
+//! A terminal game of Connect Four against the computer
+
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    connect4::{Color, Game, WIDTH},
+    connect4_eval::{EvalConfig, Weighted},
+    connect4_history::History,
+    game::{
+        search::{Algorithm, SearchLimits, Searcher},
+        transposition::TranspositionTable,
+        MinimaxGame,
+    },
+};
+
+pub struct PlayOptions {
+    /// The color the human plays. White moves first
+    pub human: Color,
+    /// How many moves ahead the computer searches
+    pub depth: usize,
+    /// The weights the computer evaluates positions with
+    pub eval: EvalConfig,
+}
+
+impl Default for PlayOptions {
+    fn default() -> Self {
+        PlayOptions {
+            human: Color::White,
+            depth: 7,
+            eval: EvalConfig::default(),
+        }
+    }
+}
+
+/// Plays a game reading the human's moves from input, returning its history when it finishes or
+/// the human quits
+pub fn play<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    options: &PlayOptions,
+) -> io::Result<History> {
+    let mut table = TranspositionTable::new(1 << 16);
+    let mut history = History::new();
+    let mut lines = input.lines();
+
+    writeln!(
+        output,
+        "You are {}. Enter a column from 0 to {}, \"undo\" to take back your last move, \"redo\" \
+         to play it again or \"quit\"",
+        options.human,
+        WIDTH - 1
+    )?;
+    writeln!(output, "{}", history.game())?;
+
+    loop {
+        let game = history.game();
+
+        if game.has_finished() {
+            match game.winner() {
+                Some(color) if color == options.human => writeln!(output, "You win!")?,
+                Some(_) => writeln!(output, "The computer wins")?,
+                None => writeln!(output, "It's a draw")?,
+            }
+
+            return Ok(history);
+        }
+
+        if game.to_move() != options.human {
+            let result = Searcher::new(Algorithm::Negascout)
+                .with_table(&mut table)
+                .iterative_deepening(
+                    &Weighted::new(game.clone(), &options.eval),
+                    SearchLimits::depth(options.depth),
+                );
+            history.make_move(result.best_move);
+
+            writeln!(
+                output,
+                "The computer plays {}\n{}",
+                result.best_move,
+                history.game()
+            )?;
+            continue;
+        }
+
+        write!(output, "> ")?;
+        output.flush()?;
+
+        let Some(line) = lines.next() else {
+            return Ok(history);
+        };
+
+        match line?.trim() {
+            "quit" => return Ok(history),
+            "undo" => {
+                if !has_moved(&history, options.human) {
+                    writeln!(output, "There is nothing to undo")?;
+                    continue;
+                }
+
+                // Take back the human's last move along with the computer's reply
+                while history.unmake_move().is_some() {
+                    if history.game().to_move() == options.human {
+                        break;
+                    }
+                }
+                writeln!(output, "{}", history.game())?;
+            }
+            "redo" => {
+                if history.redo().is_none() {
+                    writeln!(output, "There is nothing to redo")?;
+                    continue;
+                }
+
+                // Play the computer's reply again too, if it was taken back
+                while history.game().to_move() != options.human && history.redo().is_some() {}
+                writeln!(output, "{}", history.game())?;
+            }
+            text => match parse_move(game, text) {
+                Ok(column) => {
+                    history.make_move(column);
+                    writeln!(output, "{}", history.game())?;
+                }
+                Err(message) => writeln!(output, "{}", message)?,
+            },
+        }
+    }
+}
+
+/// Returns true if the human has played a move since the start of the game
+fn has_moved(history: &History, human: Color) -> bool {
+    history
+        .replay()
+        .take(history.moves().len())
+        .any(|game| game.to_move() == human)
+}
+
+/// Checks the text is a column which can still be played
+fn parse_move(game: &Game, text: &str) -> Result<usize, String> {
+    let column: usize = text
+        .parse()
+        .map_err(|_| format!("\"{}\" is not a column", text))?;
+
+    game.check_move(column).map_err(|error| error.to_string())?;
+
+    Ok(column)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        connect4::{Color, Game},
+        game::MinimaxGame,
+    };
+
+    use super::{parse_move, play, PlayOptions};
+
+    fn run(input: &str, options: &PlayOptions) -> (Option<Color>, String) {
+        let mut output = vec![];
+        let history = play(Cursor::new(input), &mut output, options).expect("io error");
+
+        (
+            history.game().winner(),
+            String::from_utf8(output).expect("output is utf8"),
+        )
+    }
+
+    #[test]
+    pub fn parse_move_validates() {
+        let mut game = Game::new();
+        for _ in 0..6 {
+            game = game.add_piece(0);
+        }
+
+        assert_eq!(parse_move(&game, "3"), Ok(3));
+        assert_eq!(parse_move(&game, "0"), Err("column 0 is full".to_string()));
+        assert_eq!(
+            parse_move(&game, "7"),
+            Err("column 7 is off the board".to_string())
+        );
+        assert_eq!(
+            parse_move(&game, "-1"),
+            Err("\"-1\" is not a column".to_string())
+        );
+        assert_eq!(
+            parse_move(&game, "a"),
+            Err("\"a\" is not a column".to_string())
+        );
+    }
+
+    #[test]
+    pub fn undo() {
+        let options = PlayOptions {
+            human: Color::White,
+            depth: 2,
+            ..Default::default()
+        };
+        let (winner, output) = run("undo\n3\nundo\nquit\n", &options);
+
+        assert_eq!(winner, None);
+        assert!(output.contains("There is nothing to undo"));
+        assert!(output.contains("The computer plays"));
+
+        // After undoing, the empty board is shown again
+        let empty = format!("{}", Game::new());
+        assert!(output.ends_with(&format!("{}\n> ", empty)));
+    }
+
+    #[test]
+    pub fn computer_moves_first_for_black() {
+        let options = PlayOptions {
+            human: Color::Black,
+            depth: 2,
+            ..Default::default()
+        };
+        let (_, output) = run("undo\n", &options);
+
+        assert!(output.find("The computer plays") < output.find("There is nothing to undo"));
+    }
+
+    #[test]
+    pub fn plays_to_the_end() {
+        let options = PlayOptions {
+            human: Color::White,
+            depth: 4,
+            ..Default::default()
+        };
+        let input = "0\n1\n2\n3\n4\n5\n6\n".repeat(20);
+        let (winner, output) = run(&input, &options);
+
+        // Playing columns in turn is no match for the computer
+        assert_eq!(winner, Some(Color::Black));
+        assert!(output.ends_with("The computer wins\n"));
+    }
+
+    #[test]
+    pub fn redo() {
+        let options = PlayOptions {
+            human: Color::White,
+            depth: 2,
+            ..Default::default()
+        };
+        let (_, output) = run("redo\n3\nundo\nredo\nquit\n", &options);
+
+        assert!(output.contains("There is nothing to redo"));
+
+        // Redoing plays the computer's reply again rather than searching for a new one
+        assert_eq!(output.matches("The computer plays").count(), 1);
+        let boards: Vec<&str> = output.split("> ").collect();
+        assert!(boards[2].ends_with(boards[4]));
+    }
+}
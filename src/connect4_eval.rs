@@ -0,0 +1,570 @@
+//! A heuristic evaluation for Connect Four built from threats.
+//!
+//! A threat is an empty space which would complete a line of four. Which row it is on matters:
+//! near the end of a game the player moving first can usually claim spaces on the odd rows,
+//! counting from 1 at the bottom, and the player moving second the even rows, so a threat on the
+//! right row is worth much more than one on the wrong row
+
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    connect4::{
+        winning_spaces, Color, Game, MoveIterator, BOARD, BOTTOM, COLUMN_BITS, HEIGHT, WIDTH,
+    },
+    game::MinimaxGame,
+};
+
+/// The number of lines of four which fit on the board
+const WINDOW_COUNT: usize =
+    (WIDTH - 3) * HEIGHT + WIDTH * (HEIGHT - 3) + 2 * (WIDTH - 3) * (HEIGHT - 3);
+
+/// Every line of four on the board
+const WINDOWS: [u64; WINDOW_COUNT] = windows();
+
+/// The number of features a position is scored on
+pub const FEATURES: usize = 7;
+
+/// The names of the weights in a weights file, in the order of `EvalConfig::weights`
+const WEIGHT_NAMES: [&str; FEATURES] = [
+    "windows_1",
+    "windows_2",
+    "windows_3",
+    "good_threat",
+    "threat",
+    "centre",
+    "forced_block",
+];
+
+/// The odd rows counting from 1 at the bottom, where the first player's threats belong
+const ODD_ROWS: u64 = BOTTOM * 0b010101;
+
+/// The even rows, where the second player's threats belong
+const EVEN_ROWS: u64 = BOARD ^ ODD_ROWS;
+
+/// The weight given to each feature of a position. Changing them gives a different engine, so
+/// versions can be played against each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvalConfig {
+    /// Per line of four holding one, two or three of a player's pieces and none of the opponent's
+    pub windows: [i64; 3],
+    /// Per threat on a row of the player's own parity
+    pub good_threat: i64,
+    /// Per threat on a row of the opponent's parity
+    pub threat: i64,
+    /// Per piece, times the number of columns it is from the edge
+    pub centre: i64,
+    /// When the player to move has to block a threat the opponent could play next move
+    pub forced_block: i64,
+    /// The score for a position where the player to move can force a win next move. It should be
+    /// larger than any other evaluation, but smaller than a finished game
+    pub forced_win: i64,
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The features of a position, each counted for the player to move less the opponent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Features {
+    /// Lines of four holding one, two or three pieces of one color and none of the other
+    pub windows: [i64; 3],
+    /// Threats on a row of the player's own parity
+    pub good_threats: i64,
+    /// Threats on a row of the opponent's parity
+    pub threats: i64,
+    /// Pieces weighted by how many columns they are from the edge
+    pub centre: i64,
+    /// 1 if the player to move has to block a threat, otherwise 0
+    pub forced_block: i64,
+}
+
+/// What happens next move when one of the players has a win they can play
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Forced {
+    /// The player to move can complete a line
+    Win,
+    /// The opponent has more threats to play than can be blocked
+    Loss,
+    /// The player to move has to block the threat in this column
+    Block(usize),
+}
+
+impl EvalConfig {
+    /// The weights `Game` evaluates positions with
+    pub const DEFAULT: EvalConfig = EvalConfig {
+        windows: [1, 2, 4],
+        good_threat: 12,
+        threat: 2,
+        centre: 1,
+        forced_block: -4,
+        forced_win: 100_000,
+    };
+
+    /// Scores a position in progress from the point of view of the player to move
+    pub fn evaluate(&self, game: &Game) -> i64 {
+        match forced(game) {
+            Some(Forced::Win) => self.forced_win,
+            Some(Forced::Loss) => -self.forced_win,
+            _ => self.score(&Features::new(game)),
+        }
+    }
+
+    /// Weighs the features of a position
+    pub fn score(&self, features: &Features) -> i64 {
+        self.weights()
+            .iter()
+            .zip(features.values())
+            .map(|(weight, value)| weight * value)
+            .sum()
+    }
+
+    /// The weight of each feature, in the same order as `Features::values`
+    pub fn weights(&self) -> [i64; FEATURES] {
+        let [one, two, three] = self.windows;
+
+        [
+            one,
+            two,
+            three,
+            self.good_threat,
+            self.threat,
+            self.centre,
+            self.forced_block,
+        ]
+    }
+
+    /// Replaces the weight of each feature, keeping the score for forced wins
+    pub fn with_weights(self, weights: [i64; FEATURES]) -> Self {
+        let [one, two, three, good_threat, threat, centre, forced_block] = weights;
+
+        EvalConfig {
+            windows: [one, two, three],
+            good_threat,
+            threat,
+            centre,
+            forced_block,
+            forced_win: self.forced_win,
+        }
+    }
+
+    /// Writes the weights one per line, as a name followed by the value
+    pub fn save<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for (name, weight) in WEIGHT_NAMES.iter().zip(self.weights()) {
+            writeln!(output, "{} {}", name, weight)?;
+        }
+        writeln!(output, "forced_win {}", self.forced_win)
+    }
+
+    /// Reads weights written by `save`. Any which are missing keep their default value
+    pub fn load<R: BufRead>(input: R) -> io::Result<EvalConfig> {
+        let mut weights = EvalConfig::default().weights();
+        let mut forced_win = EvalConfig::default().forced_win;
+
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+            let mut parts = line.split_whitespace();
+            let (Some(name), Some(value), None) = (parts.next(), parts.next(), parts.next()) else {
+                return Err(invalid(format!(
+                    "expected a name and a weight but got \"{}\"",
+                    line
+                )));
+            };
+            let value = value
+                .parse()
+                .map_err(|_| invalid(format!("\"{}\" is not a weight", value)))?;
+
+            match WEIGHT_NAMES.iter().position(|known| *known == name) {
+                Some(index) => weights[index] = value,
+                None if name == "forced_win" => forced_win = value,
+                None => return Err(invalid(format!("unknown weight \"{}\"", name))),
+            }
+        }
+
+        Ok(EvalConfig {
+            forced_win,
+            ..EvalConfig::default().with_weights(weights)
+        })
+    }
+}
+
+impl Features {
+    /// The value of each feature, in the same order as `EvalConfig::weights`
+    pub fn values(&self) -> [i64; FEATURES] {
+        let [one, two, three] = self.windows;
+
+        [
+            one,
+            two,
+            three,
+            self.good_threats,
+            self.threats,
+            self.centre,
+            self.forced_block,
+        ]
+    }
+
+    pub fn new(game: &Game) -> Self {
+        let (current, mask) = game.bitboards();
+        let opponent = current ^ mask;
+        let playable = (mask + BOTTOM) & BOARD;
+
+        let mut windows = [0; 3];
+        for window in WINDOWS {
+            match (
+                (window & current).count_ones(),
+                (window & opponent).count_ones(),
+            ) {
+                // A full window is a line which has already won, which the search scores itself
+                (ours @ 1..=3, 0) => windows[ours as usize - 1] += 1,
+                (0, theirs @ 1..=3) => windows[theirs as usize - 1] -= 1,
+                _ => {}
+            }
+        }
+
+        let (our_rows, their_rows) = if game.to_move() == Color::White {
+            (ODD_ROWS, EVEN_ROWS)
+        } else {
+            (EVEN_ROWS, ODD_ROWS)
+        };
+
+        // Threats which can be played next move are forced wins or blocks instead
+        let ours = winning_spaces(current, mask) & !playable;
+        let theirs = winning_spaces(opponent, mask) & !playable;
+        let count = |spaces: u64| spaces.count_ones() as i64;
+
+        let centre = (0..WIDTH)
+            .map(|column| {
+                let pieces = ((1 << HEIGHT) - 1) << (column * COLUMN_BITS);
+                let distance = column.min(WIDTH - 1 - column) as i64;
+                distance * (count(current & pieces) - count(opponent & pieces))
+            })
+            .sum();
+
+        Features {
+            windows,
+            good_threats: count(ours & our_rows) - count(theirs & their_rows),
+            threats: count(ours & their_rows) - count(theirs & our_rows),
+            centre,
+            forced_block: matches!(forced(game), Some(Forced::Block(_))) as i64,
+        }
+    }
+}
+
+/// A position searched with other weights than the defaults. The weights are borrowed, so the
+/// position stays the same and they aren't copied with every move
+#[derive(Debug, Clone)]
+pub struct Weighted<'e> {
+    game: Game,
+    eval: &'e EvalConfig,
+}
+
+impl<'e> Weighted<'e> {
+    pub fn new(game: Game, eval: &'e EvalConfig) -> Self {
+        Weighted { game, eval }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+}
+
+impl MinimaxGame for Weighted<'_> {
+    type Move = usize;
+    type Moves = MoveIterator;
+    type Player = Color;
+
+    fn to_move(&self) -> Color {
+        self.game.to_move()
+    }
+
+    fn evaluate(&self, depth: usize) -> i64 {
+        if self.game.has_won() {
+            return self.game.evaluate(depth);
+        }
+
+        self.eval.evaluate(&self.game)
+    }
+
+    fn has_finished(&self) -> bool {
+        self.game.has_finished()
+    }
+
+    fn winner(&self) -> Option<Color> {
+        self.game.winner()
+    }
+
+    fn legal_moves(&self) -> MoveIterator {
+        self.game.legal_moves()
+    }
+
+    fn apply(&self, mv: usize) -> Self {
+        Weighted {
+            game: self.game.apply(mv),
+            eval: self.eval,
+        }
+    }
+
+    fn zobrist_key(&self) -> u64 {
+        self.game.zobrist_key()
+    }
+
+    fn order_moves(&self, moves: &mut [usize]) {
+        self.game.order_moves(moves);
+    }
+}
+
+/// Finds a win either player can play next move, and whether the player to move can stop the
+/// opponent's
+pub fn forced(game: &Game) -> Option<Forced> {
+    let (current, mask) = game.bitboards();
+    let playable = (mask + BOTTOM) & BOARD;
+
+    if winning_spaces(current, mask) & playable != 0 {
+        return Some(Forced::Win);
+    }
+
+    let threats = winning_spaces(current ^ mask, mask);
+    let blocks = threats & playable;
+    if blocks == 0 {
+        return None;
+    }
+
+    // Two threats can't both be blocked, and neither can one stacked on top of the block
+    if blocks & (blocks - 1) != 0 || (blocks << 1) & threats != 0 {
+        return Some(Forced::Loss);
+    }
+
+    Some(Forced::Block(
+        blocks.trailing_zeros() as usize / COLUMN_BITS,
+    ))
+}
+
+const fn windows() -> [u64; WINDOW_COUNT] {
+    let mut windows = [0; WINDOW_COUNT];
+    let mut count = 0;
+
+    let mut x = 0;
+    while x < WIDTH {
+        let mut h = 0;
+        while h < HEIGHT {
+            let bit = 1 << (x * COLUMN_BITS + h);
+            let right = x + 3 < WIDTH;
+            let up = h + 3 < HEIGHT;
+            let down = h >= 3;
+
+            // Lines going right, up, up right and down right from the space
+            let mut direction = 0;
+            while direction < 4 {
+                let (fits, shift) = match direction {
+                    0 => (right, COLUMN_BITS),
+                    1 => (up, 1),
+                    2 => (right && up, COLUMN_BITS + 1),
+                    _ => (right && down, COLUMN_BITS - 1),
+                };
+
+                if fits {
+                    windows[count] = bit | bit << shift | bit << (2 * shift) | bit << (3 * shift);
+                    count += 1;
+                }
+                direction += 1;
+            }
+            h += 1;
+        }
+        x += 1;
+    }
+
+    windows
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        connect4::Game,
+        game::{
+            alpha_beta,
+            tournament::{play_match, Engine, TournamentOptions},
+            MinimaxGame,
+        },
+    };
+
+    use super::{forced, EvalConfig, Features, Forced, Weighted, WINDOWS};
+
+    /// Searches with its own evaluation weights
+    struct Configured {
+        eval: EvalConfig,
+        depth: usize,
+    }
+
+    impl Engine<Game> for Configured {
+        fn name(&self) -> String {
+            format!("configured({})", self.depth)
+        }
+
+        fn choose(&mut self, game: &Game) -> usize {
+            alpha_beta(&Weighted::new(game.clone(), &self.eval), self.depth).best_move
+        }
+    }
+
+    #[test]
+    pub fn windows_cover_the_board() {
+        assert!(WINDOWS.iter().all(|window| window.count_ones() == 4));
+
+        // The centre of the bottom row is in one vertical, four horizontal and two diagonal lines
+        let centre = 1 << (3 * 7);
+        assert_eq!(WINDOWS.iter().filter(|w| *w & centre != 0).count(), 7);
+    }
+
+    #[test]
+    pub fn features() {
+        assert_eq!(Features::new(&Game::new()), Features::default());
+
+        // x in the centre and o in the corner, with x to move again
+        let game = Game::from_moves("41").unwrap();
+        let features = Features::new(&game);
+        assert_eq!(features.windows, [7 - 3, 0, 0]);
+        assert_eq!(features.centre, 3);
+
+        // The other side sees the negation
+        let game = game.add_piece(7 - 1);
+        let features = Features::new(&game);
+        assert_eq!(features.windows, [2 - 7, -1, 0]);
+        assert_eq!(features.centre, -3);
+    }
+
+    #[test]
+    pub fn weighted() {
+        let game = Game::from_moves("4453").unwrap();
+        let default = EvalConfig::default();
+        let centre_only = EvalConfig {
+            centre: 5,
+            ..EvalConfig::default().with_weights([0; 7])
+        };
+
+        let weighted = Weighted::new(game.clone(), &default);
+        assert_eq!(weighted.evaluate(3), game.evaluate(3));
+
+        let weighted = Weighted::new(game.clone(), &centre_only);
+        assert_eq!(weighted.evaluate(3), 5 * Features::new(&game).centre);
+
+        // Moves keep the weights and match the position underneath
+        let child = weighted.apply(2);
+        assert_eq!(child.game(), &game.apply(2));
+        assert_eq!(child.zobrist_key(), game.apply(2).zobrist_key());
+        assert_eq!(child.evaluate(3), 5 * Features::new(child.game()).centre);
+    }
+
+    #[test]
+    pub fn won_positions() {
+        // x has four in a column, which is a finished game and so doesn't count as a window. x's
+        // three above the bottom piece and o's three in the next column still do, and cancel out
+        let game = Game::from_moves("1212121").unwrap();
+        let features = Features::new(&game);
+        assert_eq!(features.windows[2], 0);
+        assert!(game.evaluate(0) < -EvalConfig::default().forced_win);
+    }
+
+    #[test]
+    pub fn threat_parity() {
+        // x threatens both ends of the third row, which is odd counting from 1, so good for x
+        let game = Game::from_fen("7/7/1o5/1xxx3/1oox3/oxxoo2 x").unwrap();
+        let features = Features::new(&game);
+
+        assert_eq!(forced(&game), None);
+        assert_eq!(features.good_threats, 2);
+        assert_eq!(features.threats, 0);
+
+        // From o's side they count against
+        let game = game.add_piece(5);
+        assert_eq!(Features::new(&game).good_threats, -2);
+    }
+
+    #[test]
+    pub fn forced_moves() {
+        // x can complete the bottom row
+        assert_eq!(
+            forced(&Game::from_moves("112233").unwrap()),
+            Some(Forced::Win)
+        );
+
+        // o has to block it
+        let game = Game::from_moves("1122336").unwrap();
+        assert_eq!(forced(&game), Some(Forced::Block(3)));
+        assert_eq!(Features::new(&game).forced_block, 1);
+
+        // o can't block both ends
+        assert_eq!(
+            forced(&Game::from_moves("2233447").unwrap()),
+            Some(Forced::Loss)
+        );
+
+        assert_eq!(
+            EvalConfig::default().evaluate(&Game::from_moves("112233").unwrap()),
+            100_000
+        );
+    }
+
+    #[test]
+    pub fn threats_beat_centre_only() {
+        let centre_only = EvalConfig {
+            windows: [0; 3],
+            good_threat: 0,
+            threat: 0,
+            forced_block: 0,
+            ..EvalConfig::default()
+        };
+        let options = TournamentOptions {
+            games: 20,
+            ..Default::default()
+        };
+
+        let result = play_match(
+            &Game::new(),
+            &mut Configured {
+                eval: EvalConfig::default(),
+                depth: 4,
+            },
+            &mut Configured {
+                eval: centre_only,
+                depth: 4,
+            },
+            &options,
+        );
+
+        assert!(result.score() > 0.6, "{}", result);
+    }
+
+    #[test]
+    pub fn save_and_load() {
+        let eval = EvalConfig {
+            windows: [2, 3, 5],
+            forced_block: -7,
+            forced_win: 5000,
+            ..EvalConfig::default()
+        };
+        let mut saved = vec![];
+        eval.save(&mut saved).unwrap();
+
+        let text = String::from_utf8(saved).unwrap();
+        assert!(text.starts_with("windows_1 2\nwindows_2 3\n"));
+        assert_eq!(EvalConfig::load(Cursor::new(&text)).unwrap(), eval);
+
+        // Missing weights are left at their defaults
+        let loaded = EvalConfig::load(Cursor::new("centre 9\n")).unwrap();
+        assert_eq!(loaded.centre, 9);
+        assert_eq!(loaded.windows, EvalConfig::default().windows);
+
+        assert!(EvalConfig::load(Cursor::new("centre\n")).is_err());
+        assert!(EvalConfig::load(Cursor::new("centre x\n")).is_err());
+        assert!(EvalConfig::load(Cursor::new("edges 1\n")).is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! A Connect Four game played in place, keeping every move so they can be taken back, played
+//! again or written out as a record of the game
+
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    connect4::{Color, Game},
+    game::MinimaxGame,
+};
+
+pub struct History {
+    start: Game,
+    game: Game,
+    /// The columns played since the start, in order
+    moves: Vec<usize>,
+    /// Moves which were taken back, with the most recent last
+    undone: Vec<usize>,
+    /// Seconds since the Unix epoch when the game began
+    started: u64,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::from_game(Game::new())
+    }
+
+    /// Starts the history from a position part way through a game
+    pub fn from_game(start: Game) -> Self {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+
+        History {
+            game: start.clone(),
+            start,
+            moves: vec![],
+            undone: vec![],
+            started,
+        }
+    }
+
+    /// The position after the moves played so far
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// The columns played since the start, in order
+    pub fn moves(&self) -> &[usize] {
+        &self.moves
+    }
+
+    pub fn last_move(&self) -> Option<usize> {
+        self.moves.last().copied()
+    }
+
+    /// Plays a column, which must not be full, for the player to move. Anything taken back can no
+    /// longer be redone
+    pub fn make_move(&mut self, column: usize) {
+        self.game.play(column);
+        self.moves.push(column);
+        self.undone.clear();
+    }
+
+    /// Takes back the last move, returning its column, or None at the start of the game
+    pub fn unmake_move(&mut self) -> Option<usize> {
+        let column = self.moves.pop()?;
+        self.game.undo(column);
+        self.undone.push(column);
+
+        Some(column)
+    }
+
+    /// Plays the last move taken back again, returning its column
+    pub fn redo(&mut self) -> Option<usize> {
+        let column = self.undone.pop()?;
+        self.game.play(column);
+        self.moves.push(column);
+
+        Some(column)
+    }
+
+    /// Every position from the start to the current one, each once and in the order they were
+    /// reached
+    pub fn replay(&self) -> impl Iterator<Item = Game> + '_ {
+        let mut game = self.start.clone();
+        let mut moves = self.moves.iter();
+
+        std::iter::once(game.clone()).chain(std::iter::from_fn(move || {
+            game.play(*moves.next()?);
+            Some(game.clone())
+        }))
+    }
+
+    /// Writes down the game so far
+    pub fn record(&self) -> Record {
+        Record {
+            timestamp: self.started,
+            start: self.start.to_fen(),
+            moves: self
+                .moves
+                .iter()
+                .map(|column| char::from_digit(*column as u32 + 1, 10).expect("columns are digits"))
+                .collect(),
+            result: GameResult::of(&self.game),
+        }
+    }
+}
+
+/// How a game ended. It is written as in chess, so 1-0 is a win for x, who moves first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    Winner(Color),
+    Draw,
+    Unfinished,
+}
+
+impl GameResult {
+    pub fn of(game: &Game) -> Self {
+        match game.winner() {
+            Some(color) => GameResult::Winner(color),
+            None if game.has_finished() => GameResult::Draw,
+            None => GameResult::Unfinished,
+        }
+    }
+}
+
+impl Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameResult::Winner(Color::White) => write!(f, "1-0"),
+            GameResult::Winner(Color::Black) => write!(f, "0-1"),
+            GameResult::Draw => write!(f, "1/2-1/2"),
+            GameResult::Unfinished => write!(f, "*"),
+        }
+    }
+}
+
+impl FromStr for GameResult {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1-0" => Ok(GameResult::Winner(Color::White)),
+            "0-1" => Ok(GameResult::Winner(Color::Black)),
+            "1/2-1/2" => Ok(GameResult::Draw),
+            "*" => Ok(GameResult::Unfinished),
+            _ => Err(format!("\"{}\" is not a result", s)),
+        }
+    }
+}
+
+/// A finished or unfinished game written down, one field per line:
+///
+/// ```text
+/// Timestamp 1700000000
+/// Start 7/7/7/7/7/7 x
+/// Moves 4453
+/// Result *
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Seconds since the Unix epoch when the game began
+    pub timestamp: u64,
+    /// The starting position in FEN
+    pub start: String,
+    /// The columns played, numbered from 1
+    pub moves: String,
+    pub result: GameResult,
+}
+
+impl Record {
+    /// Plays the moves again from the start, checking they are legal and match the result
+    pub fn replay(&self) -> Result<History, String> {
+        let start = Game::from_fen(&self.start).map_err(|error| error.to_string())?;
+
+        let mut history = History::from_game(start);
+        history.started = self.timestamp;
+        let columns = history
+            .game
+            .parse_moves(&self.moves)
+            .map_err(|error| error.to_string())?;
+        for column in columns {
+            history.make_move(column);
+        }
+
+        if GameResult::of(history.game()) != self.result {
+            return Err(format!(
+                "the moves end in {} rather than {}",
+                GameResult::of(history.game()),
+                self.result
+            ));
+        }
+
+        Ok(history)
+    }
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Timestamp {}", self.timestamp)?;
+        writeln!(f, "Start {}", self.start)?;
+        writeln!(f, "Moves {}", self.moves)?;
+        writeln!(f, "Result {}", self.result)
+    }
+}
+
+impl FromStr for Record {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        let mut field = |name: &str| {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name))
+                .and_then(|value| value.strip_prefix(' '))
+                .ok_or(format!("expected a line starting {}", name))
+        };
+
+        Ok(Record {
+            timestamp: field("Timestamp")?
+                .parse()
+                .map_err(|_| "the timestamp should be a number of seconds".to_string())?,
+            start: field("Start")?.to_string(),
+            moves: field("Moves")?.to_string(),
+            result: field("Result")?.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::connect4::{Color, Game};
+
+    use super::{GameResult, History, Record};
+
+    fn play(moves: &[usize]) -> History {
+        let mut history = History::new();
+        for column in moves {
+            history.make_move(*column);
+        }
+
+        history
+    }
+
+    #[test]
+    pub fn make_and_unmake_moves() {
+        let mut history = play(&[3, 3, 4]);
+
+        assert_eq!(history.game(), &Game::from_moves("445").unwrap());
+        assert_eq!(history.last_move(), Some(4));
+
+        assert_eq!(history.unmake_move(), Some(4));
+        assert_eq!(history.unmake_move(), Some(3));
+        assert_eq!(history.game(), &Game::from_moves("4").unwrap());
+
+        assert_eq!(history.redo(), Some(3));
+        assert_eq!(history.moves(), &[3, 3]);
+
+        // A new move replaces the ones taken back
+        history.make_move(0);
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.game(), &Game::from_moves("441").unwrap());
+
+        while history.unmake_move().is_some() {}
+        assert_eq!(history.game(), &Game::new());
+    }
+
+    #[test]
+    pub fn replay() {
+        let history = play(&[3, 3, 4]);
+        let positions: Vec<Game> = history.replay().collect();
+
+        assert_eq!(
+            positions,
+            ["", "4", "44", "445"].map(|moves| Game::from_moves(moves).unwrap())
+        );
+    }
+
+    #[test]
+    pub fn record() {
+        let history = play(&[0, 1, 0, 1, 0, 1, 0]);
+        let mut record = history.record();
+
+        assert_eq!(record.result, GameResult::Winner(Color::White));
+        assert_eq!(record.moves, "1212121");
+
+        record.timestamp = 1_700_000_000;
+        let text = record.to_string();
+        assert_eq!(
+            text,
+            "Timestamp 1700000000\n\
+             Start 7/7/7/7/7/7 x\n\
+             Moves 1212121\n\
+             Result 1-0\n"
+        );
+
+        let read: Record = text.parse().unwrap();
+        assert_eq!(read, record);
+
+        let replayed = read.replay().unwrap();
+        assert_eq!(replayed.game(), history.game());
+        assert_eq!(replayed.record(), record);
+    }
+
+    #[test]
+    pub fn record_errors() {
+        let record = |moves: &str, result: &str| {
+            format!(
+                "Timestamp 0\nStart 7/7/7/7/7/7 x\nMoves {}\nResult {}\n",
+                moves, result
+            )
+            .parse::<Record>()
+            .and_then(|record| record.replay())
+            .err()
+        };
+
+        assert_eq!(record("4453", "*"), None);
+        assert_eq!(
+            record("4453", "1-0"),
+            Some("the moves end in * rather than 1-0".to_string())
+        );
+        assert!(record("12121212", "1-0").is_some());
+        assert!(record("48", "*").is_some());
+        assert_eq!(
+            record("4453", "win"),
+            Some("\"win\" is not a result".to_string())
+        );
+        assert!("Moves 4453".parse::<Record>().is_err());
+    }
+}
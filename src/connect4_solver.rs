@@ -0,0 +1,718 @@
+//! An exact solver for Connect Four, which finds the game theoretic value of a position rather
+//! than a heuristic estimate.
+//!
+//! Scores follow the usual convention: 0 is a draw, and a positive score means the player to move
+//! wins with their `22 - score`th piece, so faster wins score higher. Negative scores are losses
+//! the same way round
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    connect4::{winning_spaces, Game, BOARD, BOTTOM, COLUMN_BITS, HEIGHT, WIDTH},
+    game::{
+        transposition::{Bound, Entry, Replacement, TranspositionTable},
+        MinimaxGame,
+    },
+    random,
+};
+
+const SPACES: usize = WIDTH * HEIGHT;
+
+/// The lowest score possible, losing to the opponent's fourth piece
+pub const MIN_SCORE: i64 = -(SPACES as i64) / 2 + 3;
+
+/// The highest score possible, winning with the fourth piece
+pub const MAX_SCORE: i64 = (SPACES as i64 + 1) / 2 - 3;
+
+/// Columns from the centre outwards, as central columns are more often best
+const COLUMN_ORDER: [usize; WIDTH] = column_order();
+
+/// The outcome of a position with perfect play
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// The exact value of a position, from the point of view of the player to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solution {
+    pub score: i64,
+    /// The number of pieces played before the position
+    pub moves: usize,
+}
+
+impl Solution {
+    pub fn outcome(&self) -> Outcome {
+        match self.score {
+            0 => Outcome::Draw,
+            score if score > 0 => Outcome::Win,
+            _ => Outcome::Loss,
+        }
+    }
+
+    /// The number of moves left to play, counting both players, before the winner gets four in a
+    /// row. Draws fill the board
+    pub fn plies_to_end(&self) -> usize {
+        let last_piece = SPACES.div_ceil(2) + 1;
+
+        match self.outcome() {
+            Outcome::Draw => SPACES - self.moves,
+            Outcome::Win => {
+                let pieces = last_piece - self.score as usize;
+                2 * (pieces - self.moves / 2) - 1
+            }
+            Outcome::Loss => {
+                let pieces = last_piece - (-self.score) as usize;
+                2 * (pieces - self.moves.div_ceil(2))
+            }
+        }
+    }
+}
+
+/// The parts of a position the solver needs, as bitboards in the same layout as `Game`
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    /// The pieces of the player to move
+    current: u64,
+    /// Every piece on the board
+    mask: u64,
+    moves: usize,
+}
+
+impl Position {
+    fn new(game: &Game) -> Self {
+        let (current, mask) = game.bitboards();
+
+        Position {
+            current,
+            mask,
+            moves: game.moves(),
+        }
+    }
+
+    /// A unique key for the position. Adding the two boards sets the bit above each column's
+    /// pieces, which tells columns of the same pieces at different heights apart
+    fn key(&self) -> u64 {
+        self.current + self.mask
+    }
+
+    /// The key of the position reflected left to right
+    fn mirrored_key(&self) -> u64 {
+        mirror(self.key())
+    }
+
+    /// Plays the move given as the bit of the space it fills
+    fn play(&mut self, mv: u64) {
+        self.current ^= self.mask;
+        self.mask |= mv;
+        self.moves += 1;
+    }
+
+    /// The lowest empty space in each column that isn't full
+    fn possible(&self) -> u64 {
+        (self.mask + BOTTOM) & BOARD
+    }
+
+    fn can_win_next(&self) -> bool {
+        self.winning_spaces() & self.possible() != 0
+    }
+
+    /// Empty spaces which would complete a line for the player to move
+    fn winning_spaces(&self) -> u64 {
+        winning_spaces(self.current, self.mask)
+    }
+
+    fn opponent_winning_spaces(&self) -> u64 {
+        winning_spaces(self.current ^ self.mask, self.mask)
+    }
+
+    /// The moves which don't let the opponent win straight away. If the opponent threatens to win
+    /// in two places the position is lost and there are none
+    fn non_losing_moves(&self) -> u64 {
+        let mut possible = self.possible();
+        let opponent_wins = self.opponent_winning_spaces();
+        let forced = possible & opponent_wins;
+
+        if forced != 0 {
+            if forced & (forced - 1) != 0 {
+                return 0;
+            }
+            possible = forced;
+        }
+
+        // Never play directly below a space where the opponent would win
+        possible & !(opponent_wins >> 1)
+    }
+
+    /// Ranks a move by the number of winning spaces it would give the player
+    fn move_score(&self, mv: u64) -> u32 {
+        winning_spaces(self.current | mv, self.mask).count_ones()
+    }
+}
+
+/// Reflects a bitboard left to right
+fn mirror(board: u64) -> u64 {
+    let column = (1 << COLUMN_BITS) - 1;
+
+    (0..WIDTH).fold(0, |mirrored, x| {
+        let bits = (board >> (x * COLUMN_BITS)) & column;
+        mirrored | (bits << ((WIDTH - 1 - x) * COLUMN_BITS))
+    })
+}
+
+fn column_mask(column: usize) -> u64 {
+    ((1 << HEIGHT) - 1) << (column * COLUMN_BITS)
+}
+
+const fn column_order() -> [usize; WIDTH] {
+    let mut order = [0; WIDTH];
+    let mut i = 0;
+    while i < WIDTH {
+        // 0, 1, -1, 2, -2, ... from the centre
+        let offset = i.div_ceil(2);
+        order[i] = if i % 2 == 1 {
+            WIDTH / 2 - offset
+        } else {
+            WIDTH / 2 + offset
+        };
+        i += 1;
+    }
+
+    order
+}
+
+pub struct Solver {
+    table: TranspositionTable<()>,
+    book: Option<Book>,
+    nodes: u64,
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Solver {
+    /// A solver with a table of about a million entries, which takes around 40 MB. Deep positions
+    /// solve faster with a larger table from `with_table_size`
+    pub fn new() -> Self {
+        Self::with_table_size(1 << 20)
+    }
+
+    /// A solver with a table of at least `size` entries, rounded up to a power of two. Each entry
+    /// takes 40 bytes
+    pub fn with_table_size(size: usize) -> Self {
+        Solver {
+            table: TranspositionTable::with_replacement(size, Replacement::Always),
+            book: None,
+            nodes: 0,
+        }
+    }
+
+    /// Looks up early positions in the book rather than searching them
+    pub fn with_book(mut self, book: Book) -> Self {
+        self.book = Some(book);
+        self
+    }
+
+    /// The number of positions searched since the solver was created
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Finds the exact score of a game which hasn't finished
+    pub fn solve(&mut self, game: &Game) -> Solution {
+        Solution {
+            score: self.score(Position::new(game)),
+            moves: game.moves(),
+        }
+    }
+
+    /// Scores every column from the point of view of the player to move, or None for full columns
+    pub fn analyse(&mut self, game: &Game) -> [Option<i64>; WIDTH] {
+        let mut scores = [None; WIDTH];
+
+        for column in game.legal_moves() {
+            let child = game.add_piece(column);
+            scores[column] = Some(if child.has_won() {
+                (SPACES as i64 + 1 - game.moves() as i64) / 2
+            } else if child.has_tied() {
+                0
+            } else {
+                -self.solve(&child).score
+            });
+        }
+
+        scores
+    }
+
+    /// Returns the column with the best score, preferring the centre on ties
+    pub fn best_move(&mut self, game: &Game) -> usize {
+        let scores = self.analyse(game);
+
+        COLUMN_ORDER
+            .iter()
+            .copied()
+            .filter_map(|column| scores[column].map(|score| (column, score)))
+            .reduce(|best, (column, score)| {
+                if score > best.1 {
+                    (column, score)
+                } else {
+                    best
+                }
+            })
+            .expect("could not find move")
+            .0
+    }
+
+    /// Narrows the window around the score with null window searches, which prune much more than
+    /// a full window
+    fn score(&mut self, position: Position) -> i64 {
+        if position.can_win_next() {
+            return (SPACES as i64 + 1 - position.moves as i64) / 2;
+        }
+
+        let mut min = -(SPACES as i64 - position.moves as i64) / 2;
+        let mut max = (SPACES as i64 + 1 - position.moves as i64) / 2;
+
+        while min < max {
+            // Search nearer zero first, where the score usually is
+            let mut middle = min + (max - min) / 2;
+            if middle <= 0 && min / 2 < middle {
+                middle = min / 2;
+            } else if middle >= 0 && max / 2 > middle {
+                middle = max / 2;
+            }
+
+            let result = self.negamax(position, middle, middle + 1);
+            if result <= middle {
+                max = result;
+            } else {
+                min = result;
+            }
+        }
+
+        min
+    }
+
+    /// Returns the score if it is inside (alpha, beta), otherwise a bound on it. The player to move
+    /// must not be able to win straight away
+    fn negamax(&mut self, position: Position, mut alpha: i64, mut beta: i64) -> i64 {
+        self.nodes += 1;
+
+        let moves = position.non_losing_moves();
+        if moves == 0 {
+            // Every move lets the opponent win with their next piece
+            return -(SPACES as i64 - position.moves as i64) / 2;
+        }
+
+        if position.moves >= SPACES - 2 {
+            // Neither player can win with the last two pieces
+            return 0;
+        }
+
+        // We can't win straight away, so at best we win with our piece after next
+        let min = -(SPACES as i64 - 2 - position.moves as i64) / 2;
+        if alpha < min {
+            alpha = min;
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+
+        let mut max = (SPACES as i64 - 1 - position.moves as i64) / 2;
+
+        if let Some(score) = self.book_score(&position) {
+            return score;
+        }
+
+        let key = table_key(&position);
+        if let Some(entry) = self.table.probe(key) {
+            match entry.bound {
+                Bound::Upper => max = max.min(entry.value),
+                Bound::Lower => alpha = alpha.max(entry.value),
+                Bound::Exact => return entry.value,
+            }
+        }
+
+        if beta > max {
+            beta = max;
+        }
+        if alpha >= beta {
+            return alpha;
+        }
+
+        // Search the moves which make the most threats first, breaking ties towards the centre.
+        // Sorting is stable, so equal moves keep the column order
+        let mut ordered = [(0, 0); WIDTH];
+        let mut count = 0;
+        for column in COLUMN_ORDER {
+            let mv = moves & column_mask(column);
+            if mv != 0 {
+                ordered[count] = (mv, position.move_score(mv));
+                count += 1;
+            }
+        }
+        ordered[..count].sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+        for (mv, _) in ordered.into_iter().take(count) {
+            let mut child = position;
+            child.play(mv);
+
+            let score = -self.negamax(child, -beta, -alpha);
+            if score >= beta {
+                self.store(key, score, Bound::Lower);
+                return score;
+            }
+
+            alpha = alpha.max(score);
+        }
+
+        self.store(key, alpha, Bound::Upper);
+        alpha
+    }
+
+    fn book_score(&self, position: &Position) -> Option<i64> {
+        let book = self.book.as_ref()?;
+        if position.moves > book.depth {
+            return None;
+        }
+
+        book.score(position)
+    }
+
+    fn store(&mut self, key: u64, value: i64, bound: Bound) {
+        self.table.store(Entry {
+            key,
+            depth: 0,
+            value,
+            bound,
+            best_move: None,
+        });
+    }
+}
+
+/// Position keys fill the low bits of a word in a regular pattern, so they are mixed before
+/// indexing the table. The mixing is a bijection, so distinct positions keep distinct keys
+fn table_key(position: &Position) -> u64 {
+    random::split_mix(position.key()).1
+}
+
+/// Exact scores of early positions, which are slow to solve, computed ahead of time
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Book {
+    /// The score and a move sequence for each position, keyed by the smaller of its key and the
+    /// key of its reflection
+    entries: HashMap<u64, (String, i64)>,
+    /// The most pieces played in any position in the book
+    depth: usize,
+}
+
+impl Book {
+    /// Solves every position up to `depth` moves after the start which hasn't finished and where
+    /// the player to move can't win straight away. This is slow for early positions, so books are
+    /// meant to be generated once and saved
+    pub fn generate(solver: &mut Solver, start: &Game, depth: usize) -> Book {
+        let mut book = Book::default();
+        let moves = start
+            .to_moves()
+            .expect("the start position should be reachable");
+
+        book.add_positions(solver, start, &mut moves.clone(), depth);
+        book
+    }
+
+    fn add_positions(
+        &mut self,
+        solver: &mut Solver,
+        game: &Game,
+        moves: &mut String,
+        depth: usize,
+    ) {
+        let position = Position::new(game);
+        if game.has_finished() || position.can_win_next() {
+            return;
+        }
+
+        let key = book_key(&position);
+        if self.entries.contains_key(&key) {
+            return;
+        }
+
+        let score = solver.solve(game).score;
+        self.entries.insert(key, (moves.clone(), score));
+        self.depth = self.depth.max(game.moves());
+
+        if depth == 0 {
+            return;
+        }
+
+        for column in game.legal_moves() {
+            moves.push(char::from_digit(column as u32 + 1, 10).expect("columns are digits"));
+            self.add_positions(solver, &game.add_piece(column), moves, depth - 1);
+            moves.pop();
+        }
+    }
+
+    /// The number of positions in the book
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Looks up the score of a game, if the book has it
+    pub fn get(&self, game: &Game) -> Option<i64> {
+        self.score(&Position::new(game))
+    }
+
+    fn score(&self, position: &Position) -> Option<i64> {
+        self.entries
+            .get(&book_key(position))
+            .map(|(_, score)| *score)
+    }
+
+    /// Writes one position per line, as a move sequence followed by its score
+    pub fn save<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let mut lines: Vec<&(String, i64)> = self.entries.values().collect();
+        lines.sort();
+
+        for (moves, score) in lines {
+            writeln!(output, "{} {}", moves, score)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a book written by `save`
+    pub fn load<R: BufRead>(input: R) -> io::Result<Book> {
+        let mut book = Book::default();
+
+        for line in input.lines() {
+            let line = line?;
+            let (game, score) = parse_fixture(&line)
+                .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))?;
+            let moves = line.split_whitespace().next().unwrap_or_default();
+
+            let position = Position::new(&game);
+            book.depth = book.depth.max(position.moves);
+            book.entries
+                .insert(book_key(&position), (moves.to_string(), score));
+        }
+
+        Ok(book)
+    }
+}
+
+/// A position and a reflection of it have the same score, so they share a key in the book
+fn book_key(position: &Position) -> u64 {
+    position.key().min(position.mirrored_key())
+}
+
+/// Parses a line of a move sequence followed by a score, as in books and test position sets
+pub fn parse_fixture(line: &str) -> Result<(Game, i64), String> {
+    let mut parts = line.split_whitespace();
+    let (Some(moves), Some(score), None) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(format!("expected moves and a score but got \"{}\"", line));
+    };
+
+    let game = Game::from_moves(moves).map_err(|error| format!("{}: {}", moves, error))?;
+    let score = score
+        .parse()
+        .map_err(|_| format!("\"{}\" is not a score", score))?;
+
+    Ok((game, score))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{connect4::Game, game::MinimaxGame};
+
+    use super::{parse_fixture, Book, Outcome, Solver, SPACES};
+
+    // Positions from random games, scored by `brute_force`. They are in the same format as the
+    // published test sets, but aren't taken from them. Those are checked by
+    // `solves_published_positions` when they have been downloaded
+    const END: &str = include_str!("../data/connect4/synthetic_end.txt");
+    const MIDDLE: &str = include_str!("../data/connect4/synthetic_middle.txt");
+
+    /// Plain alpha beta over the whole game, with none of the solver's tricks
+    fn brute_force(game: &Game, mut alpha: i64, beta: i64) -> i64 {
+        let moves: Vec<usize> = game.legal_moves().collect();
+
+        if moves.iter().any(|column| game.add_piece(*column).has_won()) {
+            return (SPACES as i64 + 1 - game.moves() as i64) / 2;
+        }
+
+        let mut best = i64::MIN;
+        for column in moves {
+            let child = game.add_piece(column);
+            let score = if child.has_tied() {
+                0
+            } else {
+                -brute_force(&child, -beta, -alpha)
+            };
+
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        best
+    }
+
+    fn fixtures(text: &str) -> Vec<(Game, i64)> {
+        text.lines()
+            .map(|line| parse_fixture(line).expect("invalid fixture"))
+            .collect()
+    }
+
+    #[test]
+    pub fn end_fixtures_match_brute_force() {
+        for (game, score) in fixtures(END) {
+            assert_eq!(brute_force(&game, -100, 100), score, "{}", game.to_fen());
+        }
+    }
+
+    #[test]
+    #[ignore = "takes minutes, so run it with --release"]
+    pub fn middle_fixtures_match_brute_force() {
+        for (game, score) in fixtures(MIDDLE) {
+            assert_eq!(brute_force(&game, -100, 100), score, "{}", game.to_fen());
+        }
+    }
+
+    #[test]
+    pub fn solves_end_positions() {
+        let mut solver = Solver::with_table_size(1 << 16);
+
+        for (game, score) in fixtures(END) {
+            assert_eq!(solver.solve(&game).score, score, "{}", game.to_fen());
+        }
+    }
+
+    #[test]
+    pub fn solves_middle_positions() {
+        let mut solver = Solver::with_table_size(1 << 16);
+
+        for (game, score) in fixtures(MIDDLE) {
+            assert_eq!(solver.solve(&game).score, score, "{}", game.to_fen());
+        }
+    }
+
+    /// The first lines of a published test set in data/connect4, which has to be downloaded from
+    /// http://blog.gamesolver.org/solving-connect-four/02-test-protocol/ as it isn't checked in
+    fn published(name: &str, lines: usize) -> Vec<(Game, i64)> {
+        let path = format!("{}/data/connect4/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let text = std::fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("can't read {}: {}", path, error));
+
+        fixtures(&text).into_iter().take(lines).collect()
+    }
+
+    #[test]
+    #[ignore = "needs the published test sets in data/connect4, so run it with --release"]
+    pub fn solves_published_positions() {
+        let mut solver = Solver::new();
+
+        for name in ["Test_L3_R1", "Test_L2_R1"] {
+            for (game, score) in published(name, 100) {
+                assert_eq!(solver.solve(&game).score, score, "{}", game.to_fen());
+            }
+        }
+    }
+
+    #[test]
+    pub fn outcome_and_distance() {
+        // White has an open three along the bottom, so black can't stop both ends
+        let game = Game::from_moves("22334").unwrap();
+        let solution = Solver::with_table_size(1 << 10).solve(&game);
+
+        assert_eq!(solution.score, -18);
+        assert_eq!(solution.outcome(), Outcome::Loss);
+        assert_eq!(solution.plies_to_end(), 2);
+
+        let game = game.add_piece(6);
+        let solution = Solver::with_table_size(1 << 10).solve(&game);
+
+        assert_eq!(solution.score, 18);
+        assert_eq!(solution.outcome(), Outcome::Win);
+        assert_eq!(solution.plies_to_end(), 1);
+
+        let (game, _) = fixtures(MIDDLE)
+            .into_iter()
+            .find(|(_, score)| *score == 0)
+            .expect("a drawn fixture");
+        let solution = Solver::with_table_size(1 << 10).solve(&game);
+
+        assert_eq!(solution.outcome(), Outcome::Draw);
+        assert_eq!(solution.plies_to_end(), SPACES - game.moves());
+    }
+
+    #[test]
+    pub fn best_move() {
+        let game = Game::from_moves("223347").unwrap();
+        let mut solver = Solver::with_table_size(1 << 10);
+
+        let scores = solver.analyse(&game);
+        assert_eq!(scores[0], Some(18));
+        assert_eq!(scores[4], Some(18));
+        assert!(scores[3].unwrap() < 18);
+
+        // Both wins are equally fast, so the one nearer the centre is chosen
+        assert_eq!(solver.best_move(&game), 4);
+    }
+
+    #[test]
+    pub fn book() {
+        let (start, _) = fixtures(MIDDLE).remove(0);
+        let mut solver = Solver::with_table_size(1 << 16);
+        let book = Book::generate(&mut solver, &start, 2);
+
+        assert!(book.len() > 1);
+        assert_eq!(book.get(&start), Some(solver.solve(&start).score));
+
+        let mut saved = vec![];
+        book.save(&mut saved).unwrap();
+        let loaded = Book::load(Cursor::new(saved)).unwrap();
+        assert_eq!(loaded, book);
+
+        // The book answers straight away, without searching
+        let mut with_book = Solver::with_table_size(1 << 16).with_book(loaded);
+        assert_eq!(with_book.solve(&start), solver.solve(&start));
+        assert!(with_book.nodes() <= 2);
+    }
+
+    #[test]
+    pub fn book_matches_reflections() {
+        let game = Game::from_moves("22334").unwrap();
+        let mirrored = Game::from_moves("66554").unwrap();
+
+        let mut solver = Solver::with_table_size(1 << 10);
+        let book = Book::generate(&mut solver, &game, 0);
+
+        assert_eq!(book.len(), 1);
+        assert_eq!(book.get(&mirrored), book.get(&game));
+    }
+
+    #[test]
+    pub fn parse_fixture_errors() {
+        assert!(parse_fixture("4453 2").is_ok());
+        assert!(parse_fixture("4453").is_err());
+        assert!(parse_fixture("4453 2 1").is_err());
+        assert!(parse_fixture("4458 2").is_err());
+        assert!(parse_fixture("4453 x").is_err());
+    }
+}
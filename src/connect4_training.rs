@@ -0,0 +1,288 @@
+//! Learns the weights of the Connect Four evaluation from self-play.
+//!
+//! The engine plays itself from random openings, and every position along the way is recorded with
+//! the result the player to move went on to get. A logistic regression then fits the weights, so
+//! that `sigmoid(score / SCALE)` predicts the result of each position
+
+use crate::{
+    connect4::Game,
+    connect4_eval::{forced, EvalConfig, Features, Forced, Weighted, FEATURES},
+    game::{alpha_beta, MinimaxGame},
+    random::Rng,
+};
+
+/// The evaluation score worth one unit of log odds of winning
+const SCALE: f64 = 100.0;
+
+/// A position from self-play and how the game went for the player to move
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub features: Features,
+    /// 1 for a win, 0.5 for a draw and 0 for a loss
+    pub outcome: f64,
+}
+
+pub struct TrainingOptions {
+    /// The number of self-play games to record
+    pub games: usize,
+    /// How many moves ahead the engine searches in self-play
+    pub depth: usize,
+    /// The number of random moves at the start of each game, so the games differ
+    pub opening_moves: usize,
+    pub seed: u64,
+    /// The number of passes of gradient descent over the samples
+    pub epochs: usize,
+    pub learning_rate: f64,
+}
+
+impl Default for TrainingOptions {
+    fn default() -> Self {
+        TrainingOptions {
+            games: 200,
+            depth: 4,
+            opening_moves: 4,
+            seed: 0,
+            epochs: 500,
+            learning_rate: 0.5,
+        }
+    }
+}
+
+/// Plays the engine against itself and fits new weights to the positions it reached
+pub fn train(eval: EvalConfig, options: &TrainingOptions) -> EvalConfig {
+    fit(eval, &self_play(eval, options), options)
+}
+
+/// Plays games with both sides using the evaluation, recording every position after the opening.
+/// Positions with a win on the next move are left out, as the evaluation doesn't use the weights
+/// for them
+pub fn self_play(eval: EvalConfig, options: &TrainingOptions) -> Vec<Sample> {
+    let mut rng = Rng::new(options.seed);
+    let mut samples = vec![];
+
+    for _ in 0..options.games {
+        let mut game = Game::new();
+        for _ in 0..options.opening_moves {
+            let moves: Vec<usize> = game.legal_moves().collect();
+            if moves.is_empty() {
+                break;
+            }
+            game.play(moves[rng.below(moves.len())]);
+        }
+
+        let mut positions = vec![];
+        while !game.has_finished() {
+            if !matches!(forced(&game), Some(Forced::Win | Forced::Loss)) {
+                positions.push((Features::new(&game), game.to_move()));
+            }
+
+            let weighted = Weighted::new(game.clone(), &eval);
+            game.play(alpha_beta(&weighted, options.depth).best_move);
+        }
+
+        let winner = game.winner();
+        samples.extend(positions.into_iter().map(|(features, player)| Sample {
+            features,
+            outcome: match winner {
+                None => 0.5,
+                Some(winner) if winner == player => 1.0,
+                Some(_) => 0.0,
+            },
+        }));
+    }
+
+    samples
+}
+
+/// Fits the weights to the samples by logistic regression, starting from the given ones
+pub fn fit(eval: EvalConfig, samples: &[Sample], options: &TrainingOptions) -> EvalConfig {
+    if samples.is_empty() {
+        return eval;
+    }
+
+    // Scaling each feature to unit size lets one learning rate suit them all. Most features are
+    // differences between the players and so centred on zero, but `forced_block` is 0 or 1
+    let mut sizes = [0.0; FEATURES];
+    for sample in samples {
+        for (size, value) in sizes.iter_mut().zip(sample.features.values()) {
+            *size += (value * value) as f64;
+        }
+    }
+    let sizes = sizes.map(|size| (size / samples.len() as f64).sqrt());
+
+    let scaled: Vec<[f64; FEATURES]> = samples
+        .iter()
+        .map(|sample| {
+            let values = sample.features.values();
+            std::array::from_fn(|i| match sizes[i] {
+                0.0 => 0.0,
+                size => values[i] as f64 / size,
+            })
+        })
+        .collect();
+
+    // Weights in log odds per unit of each scaled feature
+    let mut weights: [f64; FEATURES] =
+        std::array::from_fn(|i| eval.weights()[i] as f64 * sizes[i] / SCALE);
+
+    // The edge the player to move has whatever the features are, so it isn't taken up by the
+    // weights of features that aren't centred. The evaluation has no use for it, as the leaves of a
+    // fixed depth search all have the same player to move, so it is dropped afterwards
+    let mut bias = 0.0;
+
+    for _ in 0..options.epochs {
+        let mut gradient = [0.0; FEATURES];
+        let mut bias_gradient = 0.0;
+
+        for (values, sample) in scaled.iter().zip(samples) {
+            let logit: f64 = bias + weights.iter().zip(values).map(|(w, x)| w * x).sum::<f64>();
+            let error = sigmoid(logit) - sample.outcome;
+
+            for (gradient, value) in gradient.iter_mut().zip(values) {
+                *gradient += error * value;
+            }
+            bias_gradient += error;
+        }
+
+        for (weight, gradient) in weights.iter_mut().zip(gradient) {
+            *weight -= options.learning_rate * gradient / samples.len() as f64;
+        }
+        bias -= options.learning_rate * bias_gradient / samples.len() as f64;
+    }
+
+    // Features which never appeared keep their old weight
+    let mut learned = eval.weights();
+    for i in (0..FEATURES).filter(|i| sizes[*i] > 0.0) {
+        learned[i] = (weights[i] * SCALE / sizes[i]).round() as i64;
+    }
+
+    eval.with_weights(learned)
+}
+
+/// The mean cross entropy between the evaluation's predictions and the outcomes, which `fit`
+/// minimises
+pub fn log_loss(eval: &EvalConfig, samples: &[Sample]) -> f64 {
+    let total: f64 = samples
+        .iter()
+        .map(|sample| {
+            let predicted = sigmoid(eval.score(&sample.features) as f64 / SCALE)
+                .clamp(f64::EPSILON, 1.0 - f64::EPSILON);
+
+            -(sample.outcome * predicted.ln() + (1.0 - sample.outcome) * (1.0 - predicted).ln())
+        })
+        .sum();
+
+    total / samples.len() as f64
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        connect4_eval::{EvalConfig, Features},
+        random::Rng,
+    };
+
+    use super::{fit, log_loss, self_play, sigmoid, Sample, TrainingOptions, SCALE};
+
+    /// A random whole number from -range to range
+    fn value(rng: &mut Rng, range: i64) -> i64 {
+        rng.below(2 * range as usize + 1) as i64 - range
+    }
+
+    /// Random features, with outcomes drawn with the probability the true weights predict when the
+    /// player to move has an edge of `bias` log odds
+    fn samples(truth: &EvalConfig, bias: f64) -> Vec<Sample> {
+        let mut rng = Rng::new(48);
+
+        (0..4000)
+            .map(|_| {
+                let features = Features {
+                    windows: [value(&mut rng, 10), value(&mut rng, 5), value(&mut rng, 3)],
+                    good_threats: value(&mut rng, 2),
+                    threats: value(&mut rng, 2),
+                    centre: value(&mut rng, 12),
+                    forced_block: value(&mut rng, 1).abs(),
+                };
+                let probability = sigmoid(bias + truth.score(&features) as f64 / SCALE);
+
+                Sample {
+                    features,
+                    outcome: (rng.next_f64() < probability) as u8 as f64,
+                }
+            })
+            .collect()
+    }
+
+    fn assert_recovers(truth: &EvalConfig, samples: &[Sample]) {
+        let options = TrainingOptions {
+            epochs: 300,
+            learning_rate: 2.0,
+            ..Default::default()
+        };
+        let start = EvalConfig::default();
+        let learned = fit(start, samples, &options);
+
+        assert!(log_loss(&learned, samples) < log_loss(&start, samples));
+        for (learned, truth) in learned.weights().iter().zip(truth.weights()) {
+            assert!(
+                (learned - truth).abs() <= 2 + truth.abs() / 4,
+                "{:?} {:?}",
+                learned,
+                truth
+            );
+        }
+        assert_eq!(learned.forced_win, start.forced_win);
+    }
+
+    const TRUTH: EvalConfig = EvalConfig {
+        windows: [4, 10, 20],
+        good_threat: 60,
+        threat: 20,
+        centre: 8,
+        forced_block: -40,
+        ..EvalConfig::DEFAULT
+    };
+
+    #[test]
+    pub fn fit_recovers_weights() {
+        assert_recovers(&TRUTH, &samples(&TRUTH, 0.0));
+    }
+
+    #[test]
+    pub fn fit_leaves_out_side_to_move_edge() {
+        // Without a bias term, the edge would be put down to forced_block, which is never negative
+        assert_recovers(&TRUTH, &samples(&TRUTH, 0.5));
+    }
+
+    #[test]
+    pub fn self_play_records_outcomes() {
+        let options = TrainingOptions {
+            games: 4,
+            depth: 2,
+            ..Default::default()
+        };
+        let samples = self_play(EvalConfig::default(), &options);
+
+        assert!(samples.len() > 4 * 5);
+        assert!(samples
+            .iter()
+            .all(|sample| [0.0, 0.5, 1.0].contains(&sample.outcome)));
+
+        // Not every game is drawn
+        assert!(samples.iter().any(|sample| sample.outcome != 0.5));
+
+        // The same seed gives the same games
+        assert_eq!(self_play(EvalConfig::default(), &options), samples);
+    }
+
+    #[test]
+    pub fn fit_without_samples() {
+        let eval = EvalConfig::default();
+
+        assert_eq!(fit(eval, &[], &TrainingOptions::default()), eval);
+    }
+}
@@ -1,16 +1,63 @@
-use std::cmp::max;
+use std::{fmt::Debug, hash::Hash};
 
-pub trait MinimaxGame<T: Iterator<Item = (usize, Self)>> {
+use self::{
+    search::{Algorithm, SearchResult, Searcher},
+    transposition::TranspositionTable,
+};
+
+pub mod expectiminimax;
+pub mod mcts;
+pub mod multiplayer;
+pub mod ordering;
+pub mod search;
+pub mod tournament;
+pub mod transposition;
+pub mod tree;
+
+/// Scores a win far above anything an evaluation can give a position in progress. A finished game
+/// is worth `-WIN - depth` to the player who lost, so earlier wins score higher
+pub const WIN: i64 = 1_000_000;
+
+/// A two player, zero sum game which can be searched with minimax
+pub trait MinimaxGame: Sized {
+    /// A single move, as passed to `apply`
+    type Move: Copy + Eq + Hash + Debug;
+
+    /// Iterates over the legal moves from a position
+    type Moves: Iterator<Item = Self::Move>;
+
+    /// Identifies the side to move
+    type Player: Copy + Eq;
+
+    /// Returns the player whose turn it is
+    fn to_move(&self) -> Self::Player;
+
+    /// Scores the position from the point of view of the player to move
     fn evaluate(&self, depth: usize) -> i64;
+
     fn has_finished(&self) -> bool;
-    fn get_moves(self) -> T;
+
+    /// Returns the winner of a finished game, or None for a draw or a game still in progress
+    fn winner(&self) -> Option<Self::Player>;
+
+    fn legal_moves(&self) -> Self::Moves;
+
+    /// Returns the position after the player to move plays `mv`
+    fn apply(&self, mv: Self::Move) -> Self;
+
+    /// A Zobrist hash of the position, which should be updated incrementally by `apply`
+    fn zobrist_key(&self) -> u64;
+
+    /// Sorts moves so the ones most likely to be good come first, which helps alpha beta prune.
+    /// The order should only depend on the moves, not the order they are passed in
+    fn order_moves(&self, _moves: &mut [Self::Move]) {}
 }
 
 /// Returns the best move, searching as far as depth
-pub fn minimax<T: MinimaxGame<I>, I: Iterator<Item = (usize, T)>>(game: T, depth: usize) -> usize {
+pub fn minimax<T: MinimaxGame>(game: &T, depth: usize) -> T::Move {
     let new_move = game
-        .get_moves()
-        .map(|(new_move, game)| (new_move, -1 * minimax_value(game, depth - 1)))
+        .legal_moves()
+        .map(|new_move| (new_move, -minimax_value(&game.apply(new_move), depth - 1)))
         .reduce(|acc, (new_move, value)| {
             if acc.1 < value {
                 (new_move, value)
@@ -24,75 +71,50 @@ pub fn minimax<T: MinimaxGame<I>, I: Iterator<Item = (usize, T)>>(game: T, depth
 }
 
 /// Returns the minimax value from the current node, searching as far as depth
-fn minimax_value<T: MinimaxGame<I>, I: Iterator<Item = (usize, T)>>(game: T, depth: usize) -> i64 {
-    if depth <= 0 || game.has_finished() {
+fn minimax_value<T: MinimaxGame>(game: &T, depth: usize) -> i64 {
+    if depth == 0 || game.has_finished() {
         return game.evaluate(depth);
     }
 
-    game.get_moves()
-        .map(|(_, game)| -1 * minimax_value(game, depth - 1))
+    game.legal_moves()
+        .map(|new_move| -minimax_value(&game.apply(new_move), depth - 1))
         .max()
         .expect("tried to expand game node with no more moves")
 }
 
-/// Returns the best move using alpha beta pruning, searching as far as depth
-pub fn alpha_beta<T: MinimaxGame<I>, I: Iterator<Item = (usize, T)>>(
-    game: T,
-    depth: usize,
-) -> usize {
-    let new_move = game
-        .get_moves()
-        .map(|(new_move, game)| {
-            (
-                new_move,
-                -1 * alpha_beta_value(game, depth - 1, i64::min_value() + 1, i64::max_value()),
-            )
-        })
-        .reduce(|acc, (new_move, value)| {
-            if acc.1 < value {
-                (new_move, value)
-            } else {
-                acc
-            }
-        })
-        .expect("could not find move");
-    new_move.0
+/// Searches with alpha beta pruning as far as depth, returning the best move along with the
+/// principal variation, the score of every root move and search statistics
+pub fn alpha_beta<T: MinimaxGame>(game: &T, depth: usize) -> SearchResult<T::Move> {
+    search::fixed_depth(game, depth)
 }
 
-/// Returns the minimax value from the current node using alpha beta pruning, searching as far as depth
-fn alpha_beta_value<T: MinimaxGame<I>, I: Iterator<Item = (usize, T)>>(
-    game: T,
+/// Returns the best move using alpha beta pruning, reusing values of transposed positions from the table
+pub fn alpha_beta_tt<T: MinimaxGame>(
+    game: &T,
     depth: usize,
-    mut alpha: i64,
-    beta: i64,
-) -> i64 {
-    if depth <= 0 || game.has_finished() {
-        return game.evaluate(depth);
-    }
-
-    for (_, game) in game.get_moves() {
-        // Swap and negate alpha & beta so next level maximises correctly
-        let val = -1 * alpha_beta_value(game, depth - 1, -beta, -alpha);
-
-        alpha = max(alpha, val);
-        if alpha > beta {
-            break;
-        }
-    }
-
-    alpha
+    table: &mut TranspositionTable<T::Move>,
+) -> T::Move {
+    Searcher::new(Algorithm::FailSoft)
+        .with_table(table)
+        .search(game, depth)
+        .best_move
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::game::{alpha_beta, alpha_beta_value};
+    use std::ops::Range;
 
-    use super::*;
+    use super::{
+        ordering::OrderingOptions,
+        search::{iterative_deepening, SearchLimits},
+        *,
+    };
 
     #[derive(Clone)]
     struct Tree {
         links: Vec<Node>,
         root: usize,
+        ply: usize,
     }
 
     #[derive(Clone)]
@@ -101,38 +123,20 @@ mod tests {
         Node(usize, usize),
     }
 
-    struct TreeIter {
-        tree: Tree,
-        left: usize,
-        right: usize,
-        index: usize,
-    }
-
-    impl<'a> Iterator for TreeIter {
-        type Item = (usize, Tree);
-
-        fn next(&mut self) -> Option<Self::Item> {
-            if self.index == 2 {
-                return None;
-            }
-
-            if self.index == 0 {
-                self.tree.root = self.left;
-            } else {
-                self.tree.root = self.right;
-            };
+    impl MinimaxGame for Tree {
+        type Move = usize;
+        type Moves = Range<usize>;
+        type Player = usize;
 
-            self.index += 1;
-
-            Some((self.index - 1, self.tree.clone()))
+        fn to_move(&self) -> usize {
+            self.ply % 2
         }
-    }
 
-    impl MinimaxGame<TreeIter> for Tree {
         fn evaluate(&self, _: usize) -> i64 {
             match self.links[self.root] {
                 Node::Leaf(val) => val,
-                Node::Node(_, _) => panic!("shouldn't get here"),
+                // Only reached when a depth limited search stops above the leaves
+                Node::Node(_, _) => 0,
             }
         }
 
@@ -143,17 +147,37 @@ mod tests {
             }
         }
 
-        fn get_moves(self) -> TreeIter {
+        fn winner(&self) -> Option<usize> {
+            None
+        }
+
+        fn legal_moves(&self) -> Range<usize> {
             match self.links[self.root] {
                 Node::Leaf(_) => panic!("shouldn't get here"),
-                Node::Node(left, right) => TreeIter {
-                    tree: self.clone(),
-                    left,
-                    right,
-                    index: 0,
-                },
+                Node::Node(_, _) => 0..2,
             }
         }
+
+        fn apply(&self, mv: usize) -> Self {
+            let mut tree = self.clone();
+            tree.root = match self.links[self.root] {
+                Node::Leaf(_) => panic!("shouldn't get here"),
+                Node::Node(left, right) => {
+                    if mv == 0 {
+                        left
+                    } else {
+                        right
+                    }
+                }
+            };
+            tree.ply += 1;
+
+            tree
+        }
+
+        fn zobrist_key(&self) -> u64 {
+            self.root as u64
+        }
     }
 
     struct TestData {
@@ -184,6 +208,7 @@ mod tests {
                         Node::Leaf(-6),
                     ],
                     root: 0,
+                    ply: 0,
                 },
                 minimax_value: 6,
                 next_move: 1,
@@ -208,6 +233,7 @@ mod tests {
                         Node::Leaf(-2),
                     ],
                     root: 0,
+                    ply: 0,
                 },
                 minimax_value: 6,
                 next_move: 0,
@@ -234,6 +260,7 @@ mod tests {
                         Node::Leaf(8),
                     ],
                     root: 0,
+                    ply: 0,
                 },
                 minimax_value: 7,
                 next_move: 0,
@@ -244,7 +271,7 @@ mod tests {
     #[test]
     pub fn tree_minimax_value() {
         for data in get_test_data() {
-            let value = minimax_value(data.tree, 10);
+            let value = minimax_value(&data.tree, 10);
 
             assert_eq!(value, data.minimax_value);
         }
@@ -253,7 +280,7 @@ mod tests {
     #[test]
     pub fn tree_minimax() {
         for data in get_test_data() {
-            let next_move = minimax(data.tree, 10);
+            let next_move = minimax(&data.tree, 10);
 
             assert_eq!(next_move, data.next_move);
         }
@@ -262,7 +289,7 @@ mod tests {
     #[test]
     pub fn tree_alphabeta_value() {
         for data in get_test_data() {
-            let value = alpha_beta_value(data.tree, 10, i64::min_value() + 1, i64::max_value());
+            let value = alpha_beta(&data.tree, 10).score;
 
             assert_eq!(value, data.minimax_value);
         }
@@ -271,9 +298,223 @@ mod tests {
     #[test]
     pub fn tree_alphabeta() {
         for data in get_test_data() {
-            let next_move = alpha_beta(data.tree, 10);
+            let next_move = alpha_beta(&data.tree, 10).best_move;
+
+            assert_eq!(next_move, data.next_move);
+        }
+    }
+
+    #[test]
+    pub fn tree_alphabeta_analysis() {
+        let data = get_test_data().remove(2);
+        let result = alpha_beta(&data.tree, 10);
+
+        assert_eq!(result.principal_variation, vec![0, 1]);
+        assert_eq!(result.root_scores[0], (0, 7));
+        assert_eq!(result.root_scores.len(), 2);
+        assert_eq!(result.depth, 10);
+        assert!(result.cutoffs > 0);
+        assert!(result.nodes < data.tree.links.len() as u64);
+    }
+
+    #[test]
+    pub fn tree_alphabeta_tt_value() {
+        for data in get_test_data() {
+            let mut table = TranspositionTable::new(64);
+            let value = Searcher::new(Algorithm::FailSoft)
+                .with_table(&mut table)
+                .search(&data.tree, 10)
+                .score;
+
+            assert_eq!(value, data.minimax_value);
+        }
+    }
+
+    #[test]
+    pub fn tree_alphabeta_tt() {
+        for data in get_test_data() {
+            let mut table = TranspositionTable::new(64);
+            let next_move = alpha_beta_tt(&data.tree, 10, &mut table);
+
+            assert_eq!(next_move, data.next_move);
+        }
+    }
+
+    #[test]
+    pub fn tree_iterative_deepening() {
+        for data in get_test_data() {
+            let next_move = iterative_deepening(&data.tree, SearchLimits::depth(10)).best_move;
+
+            assert_eq!(next_move, data.next_move);
+        }
+    }
+
+    #[test]
+    pub fn tree_iterative_deepening_unlimited() {
+        // The search stops by itself once every line reaches a leaf
+        for data in get_test_data() {
+            let next_move = iterative_deepening(&data.tree, SearchLimits::default()).best_move;
 
             assert_eq!(next_move, data.next_move);
         }
     }
+
+    const ALGORITHMS: [Algorithm; 5] = [
+        Algorithm::Minimax,
+        Algorithm::AlphaBeta,
+        Algorithm::FailSoft,
+        Algorithm::Negascout,
+        Algorithm::MtdF,
+    ];
+
+    #[test]
+    pub fn tree_algorithms() {
+        for algorithm in ALGORITHMS {
+            for data in get_test_data() {
+                let result = Searcher::new(algorithm).search(&data.tree, 10);
+
+                assert_eq!(result.score, data.minimax_value, "{:?}", algorithm);
+                assert_eq!(result.best_move, data.next_move, "{:?}", algorithm);
+            }
+        }
+    }
+
+    #[test]
+    pub fn tree_algorithms_with_ordering() {
+        for algorithm in ALGORITHMS {
+            for data in get_test_data() {
+                // Tree keys are only unique within a tree, so each one needs its own table
+                let mut table = TranspositionTable::new(64);
+                let result = Searcher::new(algorithm)
+                    .with_table(&mut table)
+                    .with_ordering(OrderingOptions::all())
+                    .iterative_deepening(&data.tree, SearchLimits::depth(10));
+
+                assert_eq!(result.score, data.minimax_value, "{:?}", algorithm);
+            }
+        }
+    }
+
+    #[test]
+    pub fn tree_algorithms_with_exact_root_scores() {
+        for algorithm in ALGORITHMS {
+            let data = get_test_data().remove(2);
+            let result = Searcher::new(algorithm)
+                .with_exact_root_scores()
+                .search(&data.tree, 10);
+
+            assert_eq!(
+                result.root_scores,
+                alpha_beta(&data.tree, 10).root_scores,
+                "{:?}",
+                algorithm
+            );
+        }
+    }
+
+    #[test]
+    pub fn tree_pruning_visits_fewer_nodes() {
+        let mut minimax_nodes = 0;
+        let mut alpha_beta_nodes = 0;
+
+        for data in get_test_data() {
+            let minimax = Searcher::new(Algorithm::Minimax).search(&data.tree, 10);
+            let alpha_beta = Searcher::new(Algorithm::AlphaBeta).search(&data.tree, 10);
+
+            assert_eq!(minimax.cutoffs, 0);
+            assert!(alpha_beta.nodes <= minimax.nodes);
+
+            minimax_nodes += minimax.nodes;
+            alpha_beta_nodes += alpha_beta.nodes;
+        }
+
+        assert!(alpha_beta_nodes < minimax_nodes);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one move deep")]
+    pub fn search_rejects_zero_depth() {
+        let data = get_test_data().remove(0);
+        Searcher::new(Algorithm::AlphaBeta).search(&data.tree, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one move deep")]
+    pub fn iterative_deepening_rejects_zero_depth() {
+        let data = get_test_data().remove(0);
+        let limits = SearchLimits {
+            depth: Some(0),
+            ..Default::default()
+        };
+        iterative_deepening(&data.tree, limits);
+    }
+
+    #[test]
+    pub fn tree_recording() {
+        for algorithm in &ALGORITHMS[..4] {
+            for data in get_test_data() {
+                let mut searcher = Searcher::new(*algorithm).with_tree(10);
+                let result = searcher.search(&data.tree, 10);
+                let tree = searcher.tree().unwrap();
+
+                // Every node the search counted is in the tree, with the root's value as the score
+                assert_eq!(tree.nodes().len() as u64, result.nodes, "{:?}", algorithm);
+                assert_eq!(tree.root().unwrap().value, Some(result.score));
+                assert!(tree.nodes().iter().all(|node| node.value.is_some()));
+
+                if *algorithm == Algorithm::Minimax {
+                    assert_eq!(tree.pruned(), 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn tree_recording_shows_pruning() {
+        let data = get_test_data().remove(2);
+
+        let mut minimax = Searcher::new(Algorithm::Minimax).with_tree(10);
+        minimax.search(&data.tree, 10);
+        let mut alpha_beta = Searcher::new(Algorithm::AlphaBeta).with_tree(10);
+        alpha_beta.search(&data.tree, 10);
+
+        let minimax = minimax.tree().unwrap();
+        let alpha_beta = alpha_beta.tree().unwrap();
+        assert!(alpha_beta.pruned() > 0);
+        assert!(alpha_beta.nodes().len() < minimax.nodes().len());
+
+        // The second root move is searched with a window closed by the first
+        let second = &alpha_beta.nodes()[alpha_beta.root().unwrap().children[1]];
+        assert_eq!(second.mv, Some(1));
+        assert_eq!(second.beta, -data.minimax_value);
+
+        let dot = alpha_beta.to_dot();
+        assert!(dot.starts_with("digraph search {"));
+        assert!(dot.contains("style=dashed"));
+        assert!(!minimax.to_dot().contains("style=dashed"));
+        assert!(alpha_beta.to_json().starts_with("{\"move\":null"));
+    }
+
+    #[test]
+    pub fn tree_recording_depth_limit() {
+        for algorithm in ALGORITHMS {
+            let data = get_test_data().remove(2);
+            let mut searcher = Searcher::new(algorithm).with_tree(1);
+            let result = searcher.iterative_deepening(&data.tree, SearchLimits::depth(10));
+            let tree = searcher.tree().unwrap();
+
+            assert!(tree.nodes().iter().all(|node| node.ply <= 1));
+            assert_eq!(
+                // MTD(f) also records its passes over each root move, which have no move of their own
+                tree.nodes()
+                    .iter()
+                    .filter(|node| node.ply == 1 && node.mv.is_some())
+                    .count(),
+                2,
+                "{:?}",
+                algorithm
+            );
+            assert_eq!(tree.root().unwrap().value, Some(result.score));
+        }
+    }
 }
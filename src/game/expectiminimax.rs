@@ -0,0 +1,321 @@
+//! Expectiminimax search for two player games with chance events, such as dice rolls.
+//! Values are expectations, so they are f64 rather than the i64 scores used elsewhere
+
+use super::MinimaxGame;
+
+/// A game where chance can act between the players' moves
+pub trait StochasticGame: MinimaxGame {
+    /// Returns each outcome of the chance event with its probability if chance acts next, or None
+    /// if it is a player's turn. The probabilities should sum to 1
+    fn chance_outcomes(&self) -> Option<Vec<(f64, Self)>>;
+
+    /// The largest absolute value `evaluate` can return. Star1 pruning relies on this bound
+    fn max_value(&self) -> i64;
+}
+
+/// The outcome of an expectiminimax search
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChanceSearchResult<M> {
+    pub best_move: M,
+    /// The expected value of the best move, from the point of view of the player to move
+    pub value: f64,
+    pub nodes: u64,
+}
+
+/// Returns the move with the best expected value, visiting every node as far as depth.
+/// Only player moves count towards the depth
+pub fn expectiminimax<T: StochasticGame>(game: &T, depth: usize) -> ChanceSearchResult<T::Move> {
+    search(game, depth, false)
+}
+
+/// Finds the same move as `expectiminimax`, but prunes with alpha beta at player nodes and with
+/// Star1 at chance nodes, which stops exploring outcomes once the bounds on the remaining ones
+/// show the expectation falls outside the window
+pub fn star1<T: StochasticGame>(game: &T, depth: usize) -> ChanceSearchResult<T::Move> {
+    search(game, depth, true)
+}
+
+fn search<T: StochasticGame>(game: &T, depth: usize, prune: bool) -> ChanceSearchResult<T::Move> {
+    let mut search = Search { prune, nodes: 1 };
+    let bound = game.max_value() as f64;
+    let mut best: Option<(T::Move, f64)> = None;
+
+    for new_move in game.legal_moves() {
+        let alpha = match best {
+            Some((_, value)) if prune => value,
+            _ => -bound,
+        };
+
+        let child = game.apply(new_move);
+        let value = search.relative(game, &child, depth - 1, alpha, bound);
+
+        if best.is_none_or(|(_, best)| value > best) {
+            best = Some((new_move, value));
+        }
+    }
+
+    let (best_move, value) = best.expect("could not find move");
+    ChanceSearchResult {
+        best_move,
+        value,
+        nodes: search.nodes,
+    }
+}
+
+struct Search {
+    prune: bool,
+    nodes: u64,
+}
+
+impl Search {
+    /// Returns the value of `child` from the point of view of the player to move in `parent`,
+    /// searching with the window (alpha, beta) in that point of view
+    fn relative<T: StochasticGame>(
+        &mut self,
+        parent: &T,
+        child: &T,
+        depth: usize,
+        alpha: f64,
+        beta: f64,
+    ) -> f64 {
+        if child.to_move() == parent.to_move() {
+            self.value(child, depth, alpha, beta)
+        } else {
+            -self.value(child, depth, -beta, -alpha)
+        }
+    }
+
+    fn value<T: StochasticGame>(&mut self, game: &T, depth: usize, alpha: f64, beta: f64) -> f64 {
+        self.nodes += 1;
+
+        if game.has_finished() {
+            return game.evaluate(depth) as f64;
+        }
+
+        if let Some(outcomes) = game.chance_outcomes() {
+            return self.chance(game, outcomes, depth, alpha, beta);
+        }
+
+        if depth == 0 {
+            return game.evaluate(depth) as f64;
+        }
+
+        let mut alpha = alpha;
+        let mut best = f64::NEG_INFINITY;
+
+        for new_move in game.legal_moves() {
+            let value = self.relative(game, &game.apply(new_move), depth - 1, alpha, beta);
+
+            best = best.max(value);
+            alpha = alpha.max(value);
+            if self.prune && alpha >= beta {
+                break;
+            }
+        }
+
+        best
+    }
+
+    /// Averages over the outcomes. With pruning, each outcome is searched with the window which
+    /// would let the average reach (alpha, beta) if every unsearched outcome took its extreme value
+    fn chance<T: StochasticGame>(
+        &mut self,
+        game: &T,
+        outcomes: Vec<(f64, T)>,
+        depth: usize,
+        alpha: f64,
+        beta: f64,
+    ) -> f64 {
+        let bound = game.max_value() as f64;
+        let mut remaining: f64 = outcomes.iter().map(|(probability, _)| probability).sum();
+        let mut expected = 0.0;
+
+        for (probability, outcome) in outcomes.iter() {
+            remaining -= probability;
+
+            if !self.prune {
+                expected += probability * self.relative(game, outcome, depth, -bound, bound);
+                continue;
+            }
+
+            let low = (alpha - expected - bound * remaining) / probability;
+            let high = (beta - expected + bound * remaining) / probability;
+
+            let value = self.relative(game, outcome, depth, low.max(-bound), high.min(bound));
+            expected += probability * value;
+
+            if value <= low {
+                // Even if every other outcome is as good as possible, the average is at most alpha
+                return expected + bound * remaining;
+            }
+
+            if value >= high {
+                return expected - bound * remaining;
+            }
+        }
+
+        expected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use crate::game::MinimaxGame;
+
+    use super::{expectiminimax, star1, StochasticGame};
+
+    #[derive(Clone)]
+    enum Node {
+        /// A leaf, valued from the first player's point of view
+        Leaf(i64),
+        Decision(Vec<usize>),
+        Chance(Vec<(f64, usize)>),
+    }
+
+    /// A game tree where each node records the player to move
+    #[derive(Clone)]
+    struct StochasticTree {
+        links: Vec<(usize, Node)>,
+        root: usize,
+    }
+
+    impl StochasticTree {
+        fn child(&self, index: usize) -> Self {
+            StochasticTree {
+                links: self.links.clone(),
+                root: index,
+            }
+        }
+    }
+
+    impl MinimaxGame for StochasticTree {
+        type Move = usize;
+        type Moves = Range<usize>;
+        type Player = usize;
+
+        fn to_move(&self) -> usize {
+            self.links[self.root].0
+        }
+
+        fn evaluate(&self, _: usize) -> i64 {
+            match self.links[self.root] {
+                (0, Node::Leaf(value)) => value,
+                (_, Node::Leaf(value)) => -value,
+                _ => 0,
+            }
+        }
+
+        fn has_finished(&self) -> bool {
+            matches!(self.links[self.root].1, Node::Leaf(_))
+        }
+
+        fn winner(&self) -> Option<usize> {
+            None
+        }
+
+        fn legal_moves(&self) -> Range<usize> {
+            match &self.links[self.root].1 {
+                Node::Decision(children) => 0..children.len(),
+                _ => panic!("shouldn't get here"),
+            }
+        }
+
+        fn apply(&self, mv: usize) -> Self {
+            match &self.links[self.root].1 {
+                Node::Decision(children) => self.child(children[mv]),
+                _ => panic!("shouldn't get here"),
+            }
+        }
+
+        fn zobrist_key(&self) -> u64 {
+            self.root as u64
+        }
+    }
+
+    impl StochasticGame for StochasticTree {
+        fn chance_outcomes(&self) -> Option<Vec<(f64, Self)>> {
+            match &self.links[self.root].1 {
+                Node::Chance(outcomes) => Some(
+                    outcomes
+                        .iter()
+                        .map(|(probability, index)| (*probability, self.child(*index)))
+                        .collect(),
+                ),
+                _ => None,
+            }
+        }
+
+        fn max_value(&self) -> i64 {
+            20
+        }
+    }
+
+    fn get_test_tree() -> StochasticTree {
+        StochasticTree {
+            links: vec![
+                (0, Node::Decision(vec![1, 2])),
+                (1, Node::Chance(vec![(0.5, 3), (0.5, 4)])),
+                (1, Node::Chance(vec![(0.9, 5), (0.1, 6)])),
+                (1, Node::Leaf(3)),
+                (1, Node::Decision(vec![7, 8])),
+                (1, Node::Leaf(2)),
+                (1, Node::Leaf(20)),
+                (0, Node::Leaf(4)),
+                (0, Node::Leaf(10)),
+            ],
+            root: 0,
+        }
+    }
+
+    fn get_pruning_tree() -> StochasticTree {
+        StochasticTree {
+            links: vec![
+                (0, Node::Decision(vec![1, 2])),
+                (1, Node::Chance(vec![(0.5, 3), (0.5, 4)])),
+                (1, Node::Chance(vec![(0.5, 5), (0.5, 6)])),
+                (1, Node::Leaf(16)),
+                (1, Node::Leaf(16)),
+                (1, Node::Leaf(-20)),
+                (1, Node::Decision(vec![7, 8])),
+                (0, Node::Leaf(18)),
+                (0, Node::Leaf(12)),
+            ],
+            root: 0,
+        }
+    }
+
+    #[test]
+    pub fn tree_expectiminimax() {
+        let result = expectiminimax(&get_test_tree(), 10);
+
+        // The first move averages 3 and the opponent's choice of 4, the second averages 2 and 20
+        assert_eq!(result.best_move, 1);
+        assert!((result.value - 3.8).abs() < 1e-9);
+        assert_eq!(result.nodes, 9);
+    }
+
+    #[test]
+    pub fn tree_star1() {
+        let result = star1(&get_test_tree(), 10);
+
+        assert_eq!(result.best_move, 1);
+        assert!((result.value - 3.8).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn star1_prunes_chance_nodes() {
+        let tree = get_pruning_tree();
+        let full = expectiminimax(&tree, 10);
+        let pruned = star1(&tree, 10);
+
+        assert_eq!(full.best_move, 0);
+        assert_eq!(pruned.best_move, 0);
+        assert!((full.value - 16.0).abs() < 1e-9);
+        assert!((pruned.value - 16.0).abs() < 1e-9);
+
+        // After the first outcome of the second move, the best it can average is 0
+        assert!(pruned.nodes < full.nodes);
+    }
+}
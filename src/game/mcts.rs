@@ -0,0 +1,372 @@
+//! Monte Carlo tree search, which estimates the value of moves by playing random games rather
+//! than evaluating positions
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::random::Rng;
+
+use super::MinimaxGame;
+
+/// Picks the move to play from a position during a playout
+pub type Policy<T> = fn(&T, &[<T as MinimaxGame>::Move], &mut Rng) -> <T as MinimaxGame>::Move;
+
+/// How playouts choose their moves
+pub enum Playout<T: MinimaxGame> {
+    /// Every legal move is equally likely
+    Random,
+    /// Moves are chosen by a game specific policy
+    Policy(Policy<T>),
+}
+
+impl<T: MinimaxGame> Clone for Playout<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: MinimaxGame> Copy for Playout<T> {}
+
+pub struct MctsOptions<T: MinimaxGame> {
+    /// How strongly UCT favours rarely visited moves over ones with a good win rate
+    pub exploration: f64,
+    /// Stop after this many iterations on each thread. At least one of this and the time limit
+    /// must be set
+    pub iterations: Option<u64>,
+    /// Stop after this much time
+    pub time: Option<Duration>,
+    /// The number of independent trees to search in parallel, whose root statistics are summed
+    pub threads: usize,
+    pub playout: Playout<T>,
+    pub seed: u64,
+}
+
+impl<T: MinimaxGame> Clone for MctsOptions<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: MinimaxGame> Copy for MctsOptions<T> {}
+
+impl<T: MinimaxGame> Default for MctsOptions<T> {
+    fn default() -> Self {
+        MctsOptions {
+            exploration: std::f64::consts::SQRT_2,
+            iterations: Some(1000),
+            time: None,
+            threads: 1,
+            playout: Playout::Random,
+            seed: 0,
+        }
+    }
+}
+
+/// Visit and win counts for one move from the root
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveStatistics<M> {
+    pub mv: M,
+    pub visits: u64,
+    /// Wins for the player making the move, with draws counting as half a win
+    pub wins: f64,
+}
+
+impl<M> MoveStatistics<M> {
+    pub fn win_rate(&self) -> f64 {
+        if self.visits == 0 {
+            0.0
+        } else {
+            self.wins / self.visits as f64
+        }
+    }
+}
+
+struct Node<T: MinimaxGame> {
+    mv: Option<T::Move>,
+    /// The player who made the move into this node, whose wins are counted here
+    player: Option<T::Player>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<T::Move>,
+    visits: u64,
+    wins: f64,
+}
+
+/// A search tree, which can be kept between moves so earlier work is reused
+pub struct Mcts<T: MinimaxGame> {
+    game: T,
+    nodes: Vec<Node<T>>,
+    options: MctsOptions<T>,
+    rng: Rng,
+}
+
+impl<T> Mcts<T>
+where
+    T: MinimaxGame + Clone + Send + Sync,
+    T::Move: Send,
+{
+    pub fn new(game: T, options: MctsOptions<T>) -> Self {
+        assert!(
+            options.iterations.is_some() || options.time.is_some(),
+            "a search needs an iteration or time limit to stop"
+        );
+
+        let rng = Rng::new(options.seed);
+
+        Mcts {
+            nodes: vec![Self::node(&game, None, None, None)],
+            game,
+            options,
+            rng,
+        }
+    }
+
+    /// Searches until the iteration or time limit is reached, then returns the most visited move
+    pub fn search(&mut self) -> T::Move {
+        let deadline = self.options.time.map(|time| Instant::now() + time);
+
+        if self.options.threads <= 1 {
+            self.run(deadline);
+            return self.best_move().expect("could not find move");
+        }
+
+        // Root parallelisation: every helper grows its own tree from scratch and only the root
+        // statistics are shared, which needs no locking
+        let helpers: Vec<Vec<MoveStatistics<T::Move>>> = thread::scope(|scope| {
+            let handles: Vec<_> = (1..self.options.threads)
+                .map(|i| {
+                    let mut options = self.options;
+                    options.seed = self.rng.next_u64() ^ i as u64;
+                    let game = self.game.clone();
+
+                    scope.spawn(move || {
+                        let mut helper = Mcts::new(game, options);
+                        helper.run(deadline);
+                        helper.root_statistics()
+                    })
+                })
+                .collect();
+
+            self.run(deadline);
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("search thread panicked"))
+                .collect()
+        });
+
+        let mut statistics = self.root_statistics();
+        for helper in helpers {
+            for (total, stats) in statistics.iter_mut().zip(helper) {
+                total.visits += stats.visits;
+                total.wins += stats.wins;
+            }
+        }
+
+        Self::most_visited(&statistics).expect("could not find move")
+    }
+
+    /// Returns the statistics for every legal move from the root, in the order the game lists them
+    pub fn root_statistics(&self) -> Vec<MoveStatistics<T::Move>> {
+        self.game
+            .legal_moves()
+            .map(|mv| {
+                let child = self.nodes[0]
+                    .children
+                    .iter()
+                    .map(|child| &self.nodes[*child])
+                    .find(|child| child.mv == Some(mv));
+
+                MoveStatistics {
+                    mv,
+                    visits: child.map_or(0, |child| child.visits),
+                    wins: child.map_or(0.0, |child| child.wins),
+                }
+            })
+            .collect()
+    }
+
+    /// The most visited move from the root, which is more robust than the best win rate
+    pub fn best_move(&self) -> Option<T::Move> {
+        Self::most_visited(&self.root_statistics())
+    }
+
+    /// Moves the root down to the position after `mv`, keeping the subtree already searched below it
+    pub fn advance(&mut self, mv: T::Move) {
+        self.game = self.game.apply(mv);
+
+        let child = self.nodes[0]
+            .children
+            .iter()
+            .copied()
+            .find(|child| self.nodes[*child].mv == Some(mv));
+
+        let Some(child) = child else {
+            self.nodes = vec![Self::node(&self.game, None, None, None)];
+            return;
+        };
+
+        // Copy the subtree into a new arena, so the rest of the old tree is freed
+        let mut old = std::mem::take(&mut self.nodes);
+        let mut stack: Vec<(usize, Option<usize>)> = vec![(child, None)];
+
+        while let Some((index, parent)) = stack.pop() {
+            let new_index = self.nodes.len();
+            let (mv, player) = match parent {
+                Some(parent) => {
+                    self.nodes[parent].children.push(new_index);
+                    (old[index].mv, old[index].player)
+                }
+                // The new root
+                None => (None, None),
+            };
+
+            self.nodes.push(Node {
+                mv,
+                player,
+                parent,
+                children: vec![],
+                untried: std::mem::take(&mut old[index].untried),
+                visits: old[index].visits,
+                wins: old[index].wins,
+            });
+            for child in old[index].children.iter().rev() {
+                stack.push((*child, Some(new_index)));
+            }
+        }
+    }
+
+    /// The number of nodes currently in the tree
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn run(&mut self, deadline: Option<Instant>) {
+        let mut iterations = 0;
+
+        loop {
+            if self
+                .options
+                .iterations
+                .is_some_and(|limit| iterations >= limit)
+            {
+                break;
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+
+            self.iterate();
+            iterations += 1;
+        }
+    }
+
+    /// Runs one round of selection, expansion, playout and backpropagation
+    fn iterate(&mut self) {
+        let mut index = 0;
+        let mut game = self.game.clone();
+
+        // Selection: follow UCT down through fully expanded nodes
+        while self.nodes[index].untried.is_empty() && !self.nodes[index].children.is_empty() {
+            index = self.select_child(index);
+            game = game.apply(self.nodes[index].mv.expect("only the root has no move"));
+        }
+
+        // Expansion: add one untried move
+        if !self.nodes[index].untried.is_empty() {
+            let choice = self.rng.below(self.nodes[index].untried.len());
+            let mv = self.nodes[index].untried.swap_remove(choice);
+            let player = game.to_move();
+
+            game = game.apply(mv);
+
+            let child = self.nodes.len();
+            self.nodes
+                .push(Self::node(&game, Some(mv), Some(player), Some(index)));
+            self.nodes[index].children.push(child);
+            index = child;
+        }
+
+        // Playout
+        let winner = self.playout(game);
+
+        // Backpropagation
+        let mut current = Some(index);
+        while let Some(i) = current {
+            let node = &mut self.nodes[i];
+            node.visits += 1;
+            node.wins += match (winner, node.player) {
+                (None, _) => 0.5,
+                (Some(winner), Some(player)) if winner == player => 1.0,
+                _ => 0.0,
+            };
+
+            current = node.parent;
+        }
+    }
+
+    fn select_child(&self, index: usize) -> usize {
+        let parent = &self.nodes[index];
+        let log_visits = (parent.visits as f64).ln();
+
+        let uct = |child: &Node<T>| {
+            child.wins / child.visits as f64
+                + self.options.exploration * (log_visits / child.visits as f64).sqrt()
+        };
+
+        *parent
+            .children
+            .iter()
+            .max_by(|a, b| uct(&self.nodes[**a]).total_cmp(&uct(&self.nodes[**b])))
+            .expect("fully expanded nodes have children")
+    }
+
+    /// Plays until the game finishes, returning the winner
+    fn playout(&mut self, mut game: T) -> Option<T::Player> {
+        while !game.has_finished() {
+            let moves: Vec<T::Move> = game.legal_moves().collect();
+            let mv = match self.options.playout {
+                Playout::Random => moves[self.rng.below(moves.len())],
+                Playout::Policy(policy) => policy(&game, &moves, &mut self.rng),
+            };
+
+            game = game.apply(mv);
+        }
+
+        game.winner()
+    }
+
+    fn node(
+        game: &T,
+        mv: Option<T::Move>,
+        player: Option<T::Player>,
+        parent: Option<usize>,
+    ) -> Node<T> {
+        let untried = if game.has_finished() {
+            vec![]
+        } else {
+            game.legal_moves().collect()
+        };
+
+        Node {
+            mv,
+            player,
+            parent,
+            children: vec![],
+            untried,
+            visits: 0,
+            wins: 0.0,
+        }
+    }
+
+    fn most_visited(statistics: &[MoveStatistics<T::Move>]) -> Option<T::Move> {
+        statistics
+            .iter()
+            .filter(|stats| stats.visits > 0)
+            .max_by_key(|stats| stats.visits)
+            .map(|stats| stats.mv)
+    }
+}
@@ -0,0 +1,340 @@
+//! Search for games with more than two players. Scores can't be negated between turns, so
+//! positions are evaluated to a vector holding a score for every player
+
+use std::fmt::Debug;
+
+/// A game for any number of players, who take turns in some order
+pub trait MultiplayerGame: Sized {
+    type Move: Copy + Eq + Debug;
+
+    type Moves: Iterator<Item = Self::Move>;
+
+    /// The number of players, who are numbered from 0
+    fn players(&self) -> usize;
+
+    /// Returns the player whose turn it is
+    fn to_move(&self) -> usize;
+
+    /// Scores the position for every player, indexed by player. Higher is better for that player
+    fn evaluate(&self, depth: usize) -> Vec<i64>;
+
+    fn has_finished(&self) -> bool;
+
+    fn legal_moves(&self) -> Self::Moves;
+
+    /// Returns the position after the player to move plays `mv`
+    fn apply(&self, mv: Self::Move) -> Self;
+}
+
+/// The outcome of a multiplayer search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiplayerResult<M> {
+    pub best_move: M,
+    /// The score of the best move for every player. For paranoid search, only the entry for the
+    /// player to move is meaningful
+    pub scores: Vec<i64>,
+    pub nodes: u64,
+}
+
+/// Searches with max-n, where every player maximises their own entry of the score vector.
+/// Ties go to the first move found
+pub fn max_n<T: MultiplayerGame>(game: &T, depth: usize) -> MultiplayerResult<T::Move> {
+    let mut nodes = 1;
+    let player = game.to_move();
+
+    let (best_move, scores) = game
+        .legal_moves()
+        .map(|new_move| {
+            let scores = max_n_value(&game.apply(new_move), depth - 1, &mut nodes);
+            (new_move, scores)
+        })
+        .reduce(|acc, (new_move, scores)| {
+            if acc.1[player] < scores[player] {
+                (new_move, scores)
+            } else {
+                acc
+            }
+        })
+        .expect("could not find move");
+
+    MultiplayerResult {
+        best_move,
+        scores,
+        nodes,
+    }
+}
+
+fn max_n_value<T: MultiplayerGame>(game: &T, depth: usize, nodes: &mut u64) -> Vec<i64> {
+    *nodes += 1;
+
+    if depth == 0 || game.has_finished() {
+        return game.evaluate(depth);
+    }
+
+    let player = game.to_move();
+    game.legal_moves()
+        .map(|new_move| max_n_value(&game.apply(new_move), depth - 1, nodes))
+        .reduce(|acc, scores| {
+            if acc[player] < scores[player] {
+                scores
+            } else {
+                acc
+            }
+        })
+        .expect("tried to expand game node with no more moves")
+}
+
+/// Searches assuming every other player has formed a coalition against the player to move,
+/// which reduces the game to two players so alpha beta pruning applies
+pub fn paranoid<T: MultiplayerGame>(game: &T, depth: usize) -> MultiplayerResult<T::Move> {
+    let mut search = Paranoid {
+        player: game.to_move(),
+        nodes: 1,
+    };
+    let mut best: Option<(T::Move, i64)> = None;
+
+    for new_move in game.legal_moves() {
+        let alpha = best.map_or(i64::MIN, |(_, value)| value);
+        let value = search.value(&game.apply(new_move), depth - 1, alpha, i64::MAX);
+
+        if best.is_none_or(|(_, best)| value > best) {
+            best = Some((new_move, value));
+        }
+    }
+
+    let (best_move, value) = best.expect("could not find move");
+    let mut scores = vec![0; game.players()];
+    scores[search.player] = value;
+
+    MultiplayerResult {
+        best_move,
+        scores,
+        nodes: search.nodes,
+    }
+}
+
+struct Paranoid {
+    /// The player being searched for, who maximises while everyone else minimises
+    player: usize,
+    nodes: u64,
+}
+
+impl Paranoid {
+    fn value<T: MultiplayerGame>(&mut self, game: &T, depth: usize, alpha: i64, beta: i64) -> i64 {
+        self.nodes += 1;
+
+        if depth == 0 || game.has_finished() {
+            return game.evaluate(depth)[self.player];
+        }
+
+        let maximising = game.to_move() == self.player;
+        let mut alpha = alpha;
+        let mut beta = beta;
+        let mut best = if maximising { i64::MIN } else { i64::MAX };
+
+        for new_move in game.legal_moves() {
+            let value = self.value(&game.apply(new_move), depth - 1, alpha, beta);
+
+            if maximising {
+                best = best.max(value);
+                alpha = alpha.max(value);
+            } else {
+                best = best.min(value);
+                beta = beta.min(value);
+            }
+
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::{Range, RangeInclusive};
+
+    use super::{max_n, paranoid, MultiplayerGame};
+
+    #[derive(Clone)]
+    enum Node {
+        Leaf([i64; 3]),
+        Node(usize, usize),
+    }
+
+    /// A binary game tree for three players, who move in turn
+    #[derive(Clone)]
+    struct Tree {
+        links: Vec<Node>,
+        root: usize,
+        ply: usize,
+    }
+
+    impl MultiplayerGame for Tree {
+        type Move = usize;
+        type Moves = Range<usize>;
+
+        fn players(&self) -> usize {
+            3
+        }
+
+        fn to_move(&self) -> usize {
+            self.ply % 3
+        }
+
+        fn evaluate(&self, _: usize) -> Vec<i64> {
+            match self.links[self.root] {
+                Node::Leaf(scores) => scores.to_vec(),
+                Node::Node(_, _) => vec![0; 3],
+            }
+        }
+
+        fn has_finished(&self) -> bool {
+            matches!(self.links[self.root], Node::Leaf(_))
+        }
+
+        fn legal_moves(&self) -> Range<usize> {
+            0..2
+        }
+
+        fn apply(&self, mv: usize) -> Self {
+            let mut tree = self.clone();
+            tree.root = match self.links[self.root] {
+                Node::Leaf(_) => panic!("shouldn't get here"),
+                Node::Node(left, right) => {
+                    if mv == 0 {
+                        left
+                    } else {
+                        right
+                    }
+                }
+            };
+            tree.ply += 1;
+
+            tree
+        }
+    }
+
+    fn get_test_tree() -> Tree {
+        Tree {
+            links: vec![
+                Node::Node(1, 2),
+                Node::Node(3, 4),
+                Node::Node(5, 6),
+                Node::Node(7, 8),
+                Node::Node(9, 10),
+                Node::Node(11, 12),
+                Node::Node(13, 14),
+                Node::Leaf([4, 1, 2]),
+                Node::Leaf([5, 2, 1]),
+                Node::Leaf([3, 3, 3]),
+                Node::Leaf([6, 0, 0]),
+                Node::Leaf([7, 2, 3]),
+                Node::Leaf([0, 4, 1]),
+                Node::Leaf([0, 1, 9]),
+                Node::Leaf([8, 3, 2]),
+            ],
+            root: 0,
+            ply: 0,
+        }
+    }
+
+    /// Players take one or two counters in turn, and whoever takes the last one wins
+    #[derive(Clone)]
+    struct TakeAway {
+        counters: usize,
+        players: usize,
+        ply: usize,
+    }
+
+    impl MultiplayerGame for TakeAway {
+        type Move = usize;
+        type Moves = RangeInclusive<usize>;
+
+        fn players(&self) -> usize {
+            self.players
+        }
+
+        fn to_move(&self) -> usize {
+            self.ply % self.players
+        }
+
+        fn evaluate(&self, depth: usize) -> Vec<i64> {
+            let mut scores = vec![0; self.players];
+            if self.has_finished() {
+                // The player who just moved won, and winning sooner is better
+                let winner = (self.ply + self.players - 1) % self.players;
+                scores[winner] = 100 + depth as i64;
+            }
+
+            scores
+        }
+
+        fn has_finished(&self) -> bool {
+            self.counters == 0
+        }
+
+        fn legal_moves(&self) -> RangeInclusive<usize> {
+            1..=self.counters.min(2)
+        }
+
+        fn apply(&self, mv: usize) -> Self {
+            TakeAway {
+                counters: self.counters - mv,
+                players: self.players,
+                ply: self.ply + 1,
+            }
+        }
+    }
+
+    #[test]
+    pub fn tree_max_n() {
+        // Each player takes the leaf best for themselves, so the first player ends up with 7
+        let result = max_n(&get_test_tree(), 10);
+
+        assert_eq!(result.best_move, 1);
+        assert_eq!(result.scores, vec![7, 2, 3]);
+        assert_eq!(result.nodes, 15);
+    }
+
+    #[test]
+    pub fn tree_paranoid() {
+        // Assuming the others gang up, the first move guarantees 3 while the second risks 0
+        let result = paranoid(&get_test_tree(), 10);
+
+        assert_eq!(result.best_move, 0);
+        assert_eq!(result.scores[0], 3);
+        assert!(result.nodes < max_n(&get_test_tree(), 10).nodes);
+    }
+
+    #[test]
+    pub fn take_away_wins_immediately() {
+        for counters in 1..=2 {
+            let game = TakeAway {
+                counters,
+                players: 3,
+                ply: 0,
+            };
+
+            assert_eq!(max_n(&game, 5).best_move, counters);
+            assert_eq!(paranoid(&game, 5).best_move, counters);
+        }
+    }
+
+    #[test]
+    pub fn take_away_loses_with_four_counters() {
+        // Taking one leaves the third player to win, and taking two hands the win to the second
+        let game = TakeAway {
+            counters: 4,
+            players: 3,
+            ply: 0,
+        };
+
+        let result = max_n(&game, 10);
+        assert_eq!(result.scores[0], 0);
+        assert_eq!(result.scores.iter().filter(|score| **score > 0).count(), 1);
+        assert_eq!(paranoid(&game, 10).scores[0], 0);
+    }
+}
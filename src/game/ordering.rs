@@ -0,0 +1,205 @@
+//! Move ordering heuristics. Alpha beta prunes the most when the best move at each node is searched
+//! first, so these try to guess which moves are likely to be best
+
+use std::{collections::HashMap, hash::Hash};
+
+use super::MinimaxGame;
+
+/// Which sources of ordering information to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderingOptions {
+    /// Use the game's own static ordering, from `MinimaxGame::order_moves`
+    pub game_order: bool,
+    /// Search the best move stored in the transposition table first
+    pub table_move: bool,
+    /// Try moves which caused cutoffs at the same ply elsewhere in the tree
+    pub killers: bool,
+    /// Prefer moves which have caused cutoffs anywhere in the tree, weighted by depth
+    pub history: bool,
+}
+
+impl OrderingOptions {
+    pub fn all() -> Self {
+        OrderingOptions {
+            game_order: true,
+            table_move: true,
+            killers: true,
+            history: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        OrderingOptions {
+            game_order: false,
+            table_move: false,
+            killers: false,
+            history: false,
+        }
+    }
+}
+
+impl Default for OrderingOptions {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+pub struct MoveOrdering<M> {
+    options: OrderingOptions,
+    killers: Vec<[Option<M>; 2]>,
+    history: HashMap<M, u64>,
+}
+
+impl<M: Copy + Eq + Hash> MoveOrdering<M> {
+    pub fn new(options: OrderingOptions) -> Self {
+        MoveOrdering {
+            options,
+            killers: vec![],
+            history: HashMap::new(),
+        }
+    }
+
+    pub fn options(&self) -> OrderingOptions {
+        self.options
+    }
+
+    /// Sorts moves so the most promising come first. Moves are ranked by the table move, then the
+    /// killers for this ply, then history score, with the game's static order breaking ties
+    pub fn order<T: MinimaxGame<Move = M>>(
+        &self,
+        game: &T,
+        moves: &mut [M],
+        ply: usize,
+        table_move: Option<M>,
+    ) {
+        if self.options.game_order {
+            game.order_moves(moves);
+        }
+
+        let killers = self.killers.get(ply).copied().unwrap_or([None, None]);
+
+        // Sorting is stable, so moves with equal rank keep the game's order
+        moves.sort_by_key(|mv| {
+            let tier = if self.options.table_move && table_move == Some(*mv) {
+                3
+            } else if self.options.killers && killers[0] == Some(*mv) {
+                2
+            } else if self.options.killers && killers[1] == Some(*mv) {
+                1
+            } else {
+                0
+            };
+
+            let history = if self.options.history {
+                self.history.get(mv).copied().unwrap_or(0)
+            } else {
+                0
+            };
+
+            std::cmp::Reverse((tier, history))
+        });
+    }
+
+    /// Remembers a move which caused a cutoff
+    pub fn record_cutoff(&mut self, mv: M, ply: usize, depth: usize) {
+        if self.options.killers {
+            if self.killers.len() <= ply {
+                self.killers.resize(ply + 1, [None, None]);
+            }
+
+            let killers = &mut self.killers[ply];
+            if killers[0] != Some(mv) {
+                killers[1] = killers[0];
+                killers[0] = Some(mv);
+            }
+        }
+
+        if self.options.history {
+            *self.history.entry(mv).or_default() += (depth * depth) as u64;
+        }
+    }
+
+    /// Forgets the killers and history from earlier searches
+    pub fn clear(&mut self) {
+        self.killers.clear();
+        self.history.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cmp::Reverse, ops::Range};
+
+    use crate::game::MinimaxGame;
+
+    use super::{MoveOrdering, OrderingOptions};
+
+    /// A game with five moves, which it prefers in reverse order
+    struct Reversed;
+
+    impl MinimaxGame for Reversed {
+        type Move = usize;
+        type Moves = Range<usize>;
+        type Player = ();
+
+        fn to_move(&self) {}
+
+        fn evaluate(&self, _: usize) -> i64 {
+            0
+        }
+
+        fn has_finished(&self) -> bool {
+            false
+        }
+
+        fn winner(&self) -> Option<()> {
+            None
+        }
+
+        fn legal_moves(&self) -> Range<usize> {
+            0..5
+        }
+
+        fn apply(&self, _: usize) -> Self {
+            Reversed
+        }
+
+        fn zobrist_key(&self) -> u64 {
+            0
+        }
+
+        fn order_moves(&self, moves: &mut [usize]) {
+            moves.sort_by_key(|mv| Reverse(*mv));
+        }
+    }
+
+    #[test]
+    pub fn order() {
+        let mut ordering = MoveOrdering::new(OrderingOptions::all());
+        let mut moves: Vec<usize> = Reversed.legal_moves().collect();
+
+        ordering.order(&Reversed, &mut moves, 0, None);
+        assert_eq!(moves, vec![4, 3, 2, 1, 0]);
+
+        ordering.record_cutoff(1, 0, 1);
+        ordering.record_cutoff(2, 0, 1);
+        ordering.record_cutoff(0, 3, 4);
+
+        ordering.order(&Reversed, &mut moves, 0, Some(3));
+        assert_eq!(moves, vec![3, 2, 1, 0, 4]);
+
+        // Only history applies on other plies
+        ordering.order(&Reversed, &mut moves, 1, None);
+        assert_eq!(moves, vec![0, 2, 1, 4, 3]);
+    }
+
+    #[test]
+    pub fn order_with_no_options() {
+        let mut ordering = MoveOrdering::new(OrderingOptions::none());
+        let mut moves: Vec<usize> = Reversed.legal_moves().collect();
+
+        ordering.record_cutoff(1, 0, 1);
+        ordering.order(&Reversed, &mut moves, 0, Some(3));
+        assert_eq!(moves, vec![0, 1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,704 @@
+//! Instrumented alpha beta search, including iterative deepening bounded by depth, time or node budgets
+
+use std::{
+    cmp::max,
+    fmt::{Debug, Display},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use super::{
+    ordering::{MoveOrdering, OrderingOptions},
+    transposition::{Bound, Entry, TranspositionTable},
+    tree::SearchTree,
+    MinimaxGame,
+};
+
+/// How often the clock is read, in nodes
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// Limits on how much work a search may do. Unset limits are unbounded, and a depth limit must be
+/// at least one move, as a search has to try a move to choose one
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchLimits {
+    pub depth: Option<usize>,
+    pub time: Option<Duration>,
+    pub nodes: Option<u64>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: usize) -> Self {
+        assert!(depth > 0, "a search must be at least one move deep");
+
+        SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    pub fn time(time: Duration) -> Self {
+        SearchLimits {
+            time: Some(time),
+            ..Default::default()
+        }
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        SearchLimits {
+            nodes: Some(nodes),
+            ..Default::default()
+        }
+    }
+}
+
+/// The outcome of a search, with enough detail to explain the choice of move
+#[derive(Debug, Clone)]
+pub struct SearchResult<M> {
+    pub best_move: M,
+    /// The value of the best move, from the point of view of the player to move
+    pub score: i64,
+    /// The line of play expected from both sides, starting with the best move
+    pub principal_variation: Vec<M>,
+    /// The value of every root move, in the order they were searched
+    pub root_scores: Vec<(M, i64)>,
+    /// The depth of the deepest completed search
+    pub depth: usize,
+    pub nodes: u64,
+    /// The number of times a node stopped searching its moves early
+    pub cutoffs: u64,
+}
+
+impl<M: Debug> Display for SearchResult<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "best move {:?} with score {} at depth {}",
+            self.best_move, self.score, self.depth
+        )?;
+        writeln!(f, "principal variation {:?}", self.principal_variation)?;
+
+        for (mv, score) in self.root_scores.iter() {
+            writeln!(f, "  {:?}: {}", mv, score)?;
+        }
+
+        write!(f, "{} nodes, {} cutoffs", self.nodes, self.cutoffs)
+    }
+}
+
+/// Searches to a fixed depth with alpha beta, scoring every root move exactly
+pub fn fixed_depth<T: MinimaxGame>(game: &T, depth: usize) -> SearchResult<T::Move> {
+    Searcher::new(Algorithm::AlphaBeta)
+        .with_exact_root_scores()
+        .search(game, depth)
+}
+
+/// Searches one ply deeper on each iteration with alpha beta, until a limit runs out
+pub fn iterative_deepening<T: MinimaxGame>(
+    game: &T,
+    limits: SearchLimits,
+) -> SearchResult<T::Move> {
+    Searcher::new(Algorithm::AlphaBeta).iterative_deepening(game, limits)
+}
+
+/// The search algorithms a `Searcher` can run. They all find the same minimax value, but visit
+/// different numbers of nodes on the way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Plain minimax, which visits every node
+    Minimax,
+    /// Fail hard alpha beta, which clamps values to the search window
+    AlphaBeta,
+    /// Fail soft alpha beta, which returns the best value found even when it is outside the window
+    FailSoft,
+    /// Principal variation search, which searches the first move with the full window and proves
+    /// the others worse with null windows, re-searching any that turn out better
+    Negascout,
+    /// Converges on the value with a series of null window searches, relying on a transposition
+    /// table to avoid repeating work
+    MtdF,
+}
+
+/// The size of the table MTD(f) creates when it isn't given one
+const DEFAULT_TABLE_SIZE: usize = 1 << 16;
+
+/// A transposition table which is either borrowed from the caller, or owned by the search
+enum Table<'t, M> {
+    Borrowed(&'t mut TranspositionTable<M>),
+    Owned(TranspositionTable<M>),
+}
+
+impl<M> Table<'_, M> {
+    fn get(&mut self) -> &mut TranspositionTable<M> {
+        match self {
+            Table::Borrowed(table) => table,
+            Table::Owned(table) => table,
+        }
+    }
+}
+
+/// Runs any of the search algorithms, collecting the principal variation and statistics
+pub struct Searcher<'t, M> {
+    algorithm: Algorithm,
+    table: Option<Table<'t, M>>,
+    ordering: Option<MoveOrdering<M>>,
+    tree: Option<SearchTree<M>>,
+    exact_root_scores: bool,
+    root_depth: usize,
+    guess: i64,
+    deadline: Option<Instant>,
+    node_limit: Option<u64>,
+    nodes: u64,
+    cutoffs: u64,
+    can_abort: bool,
+    aborted: bool,
+    reached_horizon: bool,
+}
+
+impl<'t, M: Copy + Eq + Hash + Debug> Searcher<'t, M> {
+    pub fn new(algorithm: Algorithm) -> Self {
+        Searcher {
+            algorithm,
+            table: None,
+            ordering: None,
+            tree: None,
+            exact_root_scores: false,
+            root_depth: 0,
+            guess: 0,
+            deadline: None,
+            node_limit: None,
+            nodes: 0,
+            cutoffs: 0,
+            can_abort: false,
+            aborted: false,
+            reached_horizon: false,
+        }
+    }
+
+    /// Caches values in the given table, which is kept between searches
+    pub fn with_table(mut self, table: &'t mut TranspositionTable<M>) -> Self {
+        self.table = Some(Table::Borrowed(table));
+        self
+    }
+
+    /// Orders moves with the given heuristics, on top of the previous principal variation which is
+    /// always searched first
+    pub fn with_ordering(mut self, options: OrderingOptions) -> Self {
+        self.ordering = Some(MoveOrdering::new(options));
+        self
+    }
+
+    /// Searches every root move with a full window, so each root score is exact. Otherwise only the
+    /// best move's score is exact, and the others may be upper bounds
+    pub fn with_exact_root_scores(mut self) -> Self {
+        self.exact_root_scores = true;
+        self
+    }
+
+    /// Records the tree each search explores, down to `max_ply` moves from the root
+    pub fn with_tree(mut self, max_ply: usize) -> Self {
+        self.tree = Some(SearchTree::new(max_ply));
+        self
+    }
+
+    /// The tree explored by the last search, if it is being recorded
+    pub fn tree(&self) -> Option<&SearchTree<M>> {
+        self.tree.as_ref()
+    }
+
+    /// Searches to a fixed depth
+    pub fn search<T: MinimaxGame<Move = M>>(&mut self, game: &T, depth: usize) -> SearchResult<M> {
+        self.start(SearchLimits::depth(depth));
+        self.root(game, depth, &[]).expect("could not find move")
+    }
+
+    /// Searches one ply deeper on each iteration until a limit runs out, returning the best move
+    /// from the deepest iteration which completed. The first iteration always completes, so a move
+    /// is returned however tight the limits are. Each iteration searches the previous principal
+    /// variation first, which gives alpha beta good move ordering along it
+    pub fn iterative_deepening<T: MinimaxGame<Move = M>>(
+        &mut self,
+        game: &T,
+        limits: SearchLimits,
+    ) -> SearchResult<M> {
+        self.start(limits);
+        let mut completed: Option<SearchResult<M>> = None;
+
+        for depth in 1..=limits.depth.unwrap_or(usize::MAX) {
+            self.reached_horizon = false;
+
+            let hint = completed
+                .as_ref()
+                .map_or(&[][..], |result| &result.principal_variation[..]);
+            // Keep the tree of the last iteration which completed
+            let previous_tree = self.tree.clone();
+            let result = self.root(game, depth, hint);
+
+            if self.aborted {
+                self.tree = previous_tree;
+                break;
+            }
+
+            completed = result;
+
+            // Every line finished before the depth limit, so searching deeper changes nothing
+            if !self.reached_horizon {
+                break;
+            }
+
+            // Only iterations after the first may be cut short
+            self.can_abort = true;
+        }
+
+        let mut result = completed.expect("could not find move");
+        result.nodes = self.nodes;
+        result.cutoffs = self.cutoffs;
+
+        result
+    }
+
+    fn start(&mut self, limits: SearchLimits) {
+        assert!(
+            limits.depth != Some(0),
+            "a search must be at least one move deep"
+        );
+
+        self.deadline = limits.time.map(|time| Instant::now() + time);
+        self.node_limit = limits.nodes;
+        self.nodes = 0;
+        self.cutoffs = 0;
+        self.guess = 0;
+        self.can_abort = false;
+        self.aborted = false;
+
+        if self.algorithm == Algorithm::MtdF && self.table.is_none() {
+            self.table = Some(Table::Owned(TranspositionTable::new(DEFAULT_TABLE_SIZE)));
+        }
+
+        if let Some(table) = self.table.as_mut() {
+            table.get().new_search();
+        }
+
+        if let Some(ordering) = self.ordering.as_mut() {
+            ordering.clear();
+        }
+    }
+
+    /// Searches each root move in turn. Returns None if there are no moves, or if the search was aborted
+    fn root<T: MinimaxGame<Move = M>>(
+        &mut self,
+        game: &T,
+        depth: usize,
+        hint: &[M],
+    ) -> Option<SearchResult<M>> {
+        self.nodes += 1;
+        self.root_depth = depth;
+
+        let mut best_value = None;
+        let mut pv = vec![];
+        let mut root_scores = vec![];
+
+        if let Some(tree) = self.tree.as_mut() {
+            tree.clear();
+            tree.enter(None, i64::MIN + 1, i64::MAX);
+        }
+
+        let table_move = self.table_move(game);
+        for new_move in self.ordered_moves(game, depth, hint, table_move) {
+            let alpha = match best_value {
+                Some(best) if !self.exact_root_scores => best,
+                _ => i64::MIN + 1,
+            };
+
+            let mut child_pv = vec![];
+            let value = -self.record(Some(new_move), -i64::MAX, -alpha, |searcher| {
+                searcher.value(
+                    &game.apply(new_move),
+                    depth - 1,
+                    -i64::MAX,
+                    -alpha,
+                    follow_hint(hint, new_move),
+                    &mut child_pv,
+                )
+            });
+
+            if self.aborted {
+                return None;
+            }
+
+            root_scores.push((new_move, value));
+            if best_value.is_none_or(|best| value > best) {
+                best_value = Some(value);
+                set_pv(&mut pv, new_move, child_pv);
+            }
+        }
+
+        if let Some(tree) = self.tree.as_mut() {
+            tree.exit(best_value);
+        }
+
+        self.guess = best_value?;
+
+        Some(SearchResult {
+            best_move: *pv.first()?,
+            score: best_value?,
+            principal_variation: pv,
+            root_scores,
+            depth,
+            nodes: self.nodes,
+            cutoffs: self.cutoffs,
+        })
+    }
+
+    /// Finds the value of a node with the configured algorithm
+    fn value<T: MinimaxGame<Move = M>>(
+        &mut self,
+        game: &T,
+        depth: usize,
+        alpha: i64,
+        beta: i64,
+        hint: &[M],
+        pv: &mut Vec<M>,
+    ) -> i64 {
+        match self.algorithm {
+            Algorithm::Minimax => self.minimax(game, depth, hint, pv),
+            Algorithm::MtdF => self.mtdf(game, depth, hint, pv),
+            _ => self.alpha_beta(game, depth, alpha, beta, hint, pv),
+        }
+    }
+
+    fn minimax<T: MinimaxGame<Move = M>>(
+        &mut self,
+        game: &T,
+        depth: usize,
+        hint: &[M],
+        pv: &mut Vec<M>,
+    ) -> i64 {
+        if let Some(value) = self.leaf(game, depth) {
+            return value;
+        }
+
+        let mut best = i64::MIN + 1;
+        for new_move in self.ordered_moves(game, depth, hint, None) {
+            let mut child_pv = vec![];
+            let val = -self.record(Some(new_move), i64::MIN + 1, i64::MAX, |searcher| {
+                searcher.minimax(
+                    &game.apply(new_move),
+                    depth - 1,
+                    follow_hint(hint, new_move),
+                    &mut child_pv,
+                )
+            });
+
+            if self.aborted {
+                return 0;
+            }
+
+            if val > best {
+                best = val;
+                set_pv(pv, new_move, child_pv);
+            }
+        }
+
+        best
+    }
+
+    /// Runs null window searches around a guess, narrowing the bounds on the value until they meet
+    fn mtdf<T: MinimaxGame<Move = M>>(
+        &mut self,
+        game: &T,
+        depth: usize,
+        hint: &[M],
+        pv: &mut Vec<M>,
+    ) -> i64 {
+        // The best guess is the value of this position from the last search, which was from the
+        // other side's point of view
+        let mut value = -self.guess;
+        let mut lower = i64::MIN + 1;
+        let mut upper = i64::MAX;
+
+        while lower < upper {
+            let beta = if value == lower { value + 1 } else { value };
+
+            pv.clear();
+            value = self.record(None, beta - 1, beta, |searcher| {
+                searcher.alpha_beta(game, depth, beta - 1, beta, hint, pv)
+            });
+
+            if self.aborted {
+                return 0;
+            }
+
+            if value < beta {
+                upper = value;
+            } else {
+                lower = value;
+            }
+        }
+
+        value
+    }
+
+    /// Alpha beta search, which is fail hard, fail soft or principal variation search depending on
+    /// the algorithm. Values are cached in the transposition table if there is one
+    fn alpha_beta<T: MinimaxGame<Move = M>>(
+        &mut self,
+        game: &T,
+        depth: usize,
+        mut alpha: i64,
+        mut beta: i64,
+        hint: &[M],
+        pv: &mut Vec<M>,
+    ) -> i64 {
+        if let Some(value) = self.leaf(game, depth) {
+            return value;
+        }
+
+        let key = game.zobrist_key();
+        let entry = self.probe(key);
+        if let Some(entry) = entry.filter(|entry| entry.depth >= depth) {
+            match entry.bound {
+                Bound::Exact => return self.table_hit(entry.value),
+                Bound::Lower => alpha = max(alpha, entry.value),
+                Bound::Upper => beta = beta.min(entry.value),
+            }
+
+            if alpha >= beta {
+                return self.table_hit(entry.value);
+            }
+        }
+
+        let original_alpha = alpha;
+        let mut best_value = i64::MIN + 1;
+        let mut best_move = None;
+
+        let table_move = entry.and_then(|entry| entry.best_move);
+        let moves = self.ordered_moves(game, depth, hint, table_move);
+
+        for (i, new_move) in moves.iter().copied().enumerate() {
+            let child = game.apply(new_move);
+            let child_hint = follow_hint(hint, new_move);
+            let mut child_pv = vec![];
+
+            let val = if self.algorithm == Algorithm::Negascout && i > 0 {
+                // Try to prove this move is no better than the best so far
+                let val = -self.record(Some(new_move), -alpha - 1, -alpha, |searcher| {
+                    searcher.alpha_beta(
+                        &child,
+                        depth - 1,
+                        -alpha - 1,
+                        -alpha,
+                        child_hint,
+                        &mut child_pv,
+                    )
+                });
+
+                if val > alpha && val < beta && !self.aborted {
+                    child_pv.clear();
+                    -self.record(Some(new_move), -beta, -alpha, |searcher| {
+                        searcher.alpha_beta(
+                            &child,
+                            depth - 1,
+                            -beta,
+                            -alpha,
+                            child_hint,
+                            &mut child_pv,
+                        )
+                    })
+                } else {
+                    val
+                }
+            } else {
+                -self.record(Some(new_move), -beta, -alpha, |searcher| {
+                    searcher.alpha_beta(&child, depth - 1, -beta, -alpha, child_hint, &mut child_pv)
+                })
+            };
+
+            if self.aborted {
+                return 0;
+            }
+
+            if best_move.is_none() || val > best_value {
+                best_value = val;
+                best_move = Some(new_move);
+            }
+
+            if val > alpha {
+                alpha = val;
+                set_pv(pv, new_move, child_pv);
+            }
+
+            if alpha >= beta {
+                self.cutoffs += 1;
+
+                let ply = self.root_depth - depth;
+                if let Some(ordering) = self.ordering.as_mut() {
+                    ordering.record_cutoff(new_move, ply, depth);
+                }
+
+                if let Some(tree) = self.tree.as_mut() {
+                    tree.prune(&moves[i + 1..]);
+                }
+
+                break;
+            }
+        }
+
+        let value = if self.algorithm == Algorithm::AlphaBeta {
+            alpha
+        } else {
+            best_value
+        };
+
+        self.store(key, depth, value, original_alpha, beta, best_move);
+
+        value
+    }
+
+    /// Adds a node for `mv` to the tree, if it is being recorded, around a search of it
+    fn record<F: FnOnce(&mut Self) -> i64>(
+        &mut self,
+        mv: Option<M>,
+        alpha: i64,
+        beta: i64,
+        search: F,
+    ) -> i64 {
+        if let Some(tree) = self.tree.as_mut() {
+            tree.enter(mv, alpha, beta);
+        }
+
+        let value = search(self);
+
+        if let Some(tree) = self.tree.as_mut() {
+            tree.exit((!self.aborted).then_some(value));
+        }
+
+        value
+    }
+
+    /// Marks the node being searched as valued by the transposition table
+    fn table_hit(&mut self, value: i64) -> i64 {
+        if let Some(tree) = self.tree.as_mut() {
+            tree.table_hit();
+        }
+
+        value
+    }
+
+    /// Counts a node, and returns its value if the search should not go any deeper from it
+    fn leaf<T: MinimaxGame<Move = M>>(&mut self, game: &T, depth: usize) -> Option<i64> {
+        if self.should_abort() {
+            return Some(0);
+        }
+
+        if game.has_finished() {
+            return Some(game.evaluate(depth));
+        }
+
+        if depth == 0 {
+            self.reached_horizon = true;
+            return Some(game.evaluate(depth));
+        }
+
+        None
+    }
+
+    fn probe(&mut self, key: u64) -> Option<Entry<M>> {
+        self.table.as_mut()?.get().probe(key)
+    }
+
+    fn table_move<T: MinimaxGame<Move = M>>(&mut self, game: &T) -> Option<M> {
+        self.probe(game.zobrist_key())?.best_move
+    }
+
+    /// Returns the legal moves in the order they should be searched, with the move suggested by
+    /// the hint first
+    fn ordered_moves<T: MinimaxGame<Move = M>>(
+        &self,
+        game: &T,
+        depth: usize,
+        hint: &[M],
+        table_move: Option<M>,
+    ) -> Vec<M> {
+        let mut moves: Vec<M> = game.legal_moves().collect();
+
+        if let Some(ordering) = self.ordering.as_ref() {
+            ordering.order(game, &mut moves, self.root_depth - depth, table_move);
+        }
+
+        if let Some(position) = hint
+            .first()
+            .and_then(|first| moves.iter().position(|mv| mv == first))
+        {
+            moves[..=position].rotate_right(1);
+        }
+
+        moves
+    }
+
+    fn store(
+        &mut self,
+        key: u64,
+        depth: usize,
+        value: i64,
+        alpha: i64,
+        beta: i64,
+        best_move: Option<M>,
+    ) {
+        let Some(table) = self.table.as_mut() else {
+            return;
+        };
+
+        let bound = if value <= alpha {
+            Bound::Upper
+        } else if value >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+
+        table.get().store(Entry {
+            key,
+            depth,
+            value,
+            bound,
+            best_move,
+        });
+    }
+
+    /// Counts a node and returns true if the search has run out of budget
+    fn should_abort(&mut self) -> bool {
+        self.nodes += 1;
+
+        if !self.can_abort || self.aborted {
+            return self.aborted;
+        }
+
+        if self.node_limit.is_some_and(|limit| self.nodes > limit) {
+            self.aborted = true;
+        }
+
+        if self.nodes.is_multiple_of(TIME_CHECK_INTERVAL)
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.aborted = true;
+        }
+
+        self.aborted
+    }
+}
+
+/// Returns the rest of the hint if `mv` follows it, or an empty hint if the search has left it
+fn follow_hint<M: Eq>(hint: &[M], mv: M) -> &[M] {
+    match hint.split_first() {
+        Some((first, rest)) if *first == mv => rest,
+        _ => &[],
+    }
+}
+
+fn set_pv<M>(pv: &mut Vec<M>, mv: M, mut child_pv: Vec<M>) {
+    pv.clear();
+    pv.push(mv);
+    pv.append(&mut child_pv);
+}
@@ -0,0 +1,632 @@
+//! Plays engines against each other to measure which is stronger, reporting results as an Elo
+//! difference and optionally stopping early with a sequential probability ratio test
+
+use std::fmt::Display;
+
+use crate::random::Rng;
+
+use super::{
+    alpha_beta,
+    mcts::{Mcts, MctsOptions},
+    minimax, MinimaxGame,
+};
+
+/// Something which can choose moves in a game
+pub trait Engine<T: MinimaxGame> {
+    fn name(&self) -> String;
+
+    fn choose(&mut self, game: &T) -> T::Move;
+}
+
+/// Plays the move found by plain minimax at a fixed depth
+pub struct MinimaxEngine {
+    pub depth: usize,
+}
+
+impl<T: MinimaxGame> Engine<T> for MinimaxEngine {
+    fn name(&self) -> String {
+        format!("minimax({})", self.depth)
+    }
+
+    fn choose(&mut self, game: &T) -> T::Move {
+        minimax(game, self.depth)
+    }
+}
+
+/// Plays the move found by alpha beta at a fixed depth
+pub struct AlphaBetaEngine {
+    pub depth: usize,
+}
+
+impl<T: MinimaxGame> Engine<T> for AlphaBetaEngine {
+    fn name(&self) -> String {
+        format!("alpha-beta({})", self.depth)
+    }
+
+    fn choose(&mut self, game: &T) -> T::Move {
+        alpha_beta(game, self.depth).best_move
+    }
+}
+
+/// Runs a fresh Monte Carlo tree search for every move
+pub struct MctsEngine<T: MinimaxGame> {
+    pub options: MctsOptions<T>,
+    rng: Rng,
+}
+
+impl<T: MinimaxGame> MctsEngine<T> {
+    pub fn new(options: MctsOptions<T>) -> Self {
+        MctsEngine {
+            rng: Rng::new(options.seed),
+            options,
+        }
+    }
+}
+
+impl<T> Engine<T> for MctsEngine<T>
+where
+    T: MinimaxGame + Clone + Send + Sync,
+    T::Move: Send,
+{
+    fn name(&self) -> String {
+        match self.options.iterations {
+            Some(iterations) => format!("mcts({})", iterations),
+            None => "mcts".to_string(),
+        }
+    }
+
+    fn choose(&mut self, game: &T) -> T::Move {
+        // Vary the seed so the engine doesn't play identically from identical positions
+        let mut options = self.options;
+        options.seed = self.rng.next_u64();
+
+        Mcts::new(game.clone(), options).search()
+    }
+}
+
+/// Plays uniformly random legal moves
+pub struct RandomEngine {
+    rng: Rng,
+}
+
+impl RandomEngine {
+    pub fn new(seed: u64) -> Self {
+        RandomEngine {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl<T: MinimaxGame> Engine<T> for RandomEngine {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+
+    fn choose(&mut self, game: &T) -> T::Move {
+        let moves: Vec<T::Move> = game.legal_moves().collect();
+        moves[self.rng.below(moves.len())]
+    }
+}
+
+/// The results of a match, from the point of view of the first engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MatchResult {
+    pub wins: u64,
+    pub draws: u64,
+    pub losses: u64,
+}
+
+impl MatchResult {
+    pub fn games(&self) -> u64 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The fraction of points scored, with draws counting as half a point. Before any games it is
+    /// an even score
+    pub fn score(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.5;
+        }
+
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    /// The Elo difference implied by the score. This is infinite if one side scored every point
+    pub fn elo(&self) -> f64 {
+        elo(self.score())
+    }
+
+    /// A 95% confidence interval for the Elo difference, from the normal approximation to the
+    /// distribution of the score. Before any games it is unbounded
+    pub fn elo_interval(&self) -> (f64, f64) {
+        if self.games() == 0 {
+            return (f64::NEG_INFINITY, f64::INFINITY);
+        }
+
+        let score = self.score();
+        let deviation = (self.variance() / self.games() as f64).sqrt();
+
+        (elo(score - 1.96 * deviation), elo(score + 1.96 * deviation))
+    }
+
+    /// The variance of the points scored in a single game
+    fn variance(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+
+        moments(self.wins as f64, self.draws as f64, self.losses as f64).1
+    }
+}
+
+/// The mean and variance of the points scored in a game, given how often each result happened
+fn moments(wins: f64, draws: f64, losses: f64) -> (f64, f64) {
+    let games = wins + draws + losses;
+    let score = (wins + draws / 2.0) / games;
+    let variance =
+        (wins * (1.0 - score).powi(2) + draws * (0.5 - score).powi(2) + losses * score.powi(2))
+            / games;
+
+    (score, variance)
+}
+
+impl Display for MatchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (low, high) = self.elo_interval();
+
+        write!(
+            f,
+            "+{} ={} -{}, Elo {:.1} [{:.1}, {:.1}]",
+            self.wins,
+            self.draws,
+            self.losses,
+            self.elo(),
+            low,
+            high
+        )
+    }
+}
+
+/// Converts an expected score into an Elo difference
+pub fn elo(score: f64) -> f64 {
+    if score <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if score >= 1.0 {
+        return f64::INFINITY;
+    }
+
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+/// Converts an Elo difference into an expected score
+pub fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// The number of games of each result the SPRT adds to those played. Without it a short streak of
+/// one result would have zero variance, and so look like certain proof of a difference
+const PSEUDO_COUNT: f64 = 0.5;
+
+/// A sequential probability ratio test between the hypotheses that the first engine is `elo0`
+/// stronger (H0) and that it is `elo1` stronger (H1), with error rates alpha and beta
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtStatus {
+    Continue,
+    AcceptH0,
+    AcceptH1,
+}
+
+impl Sprt {
+    /// The log likelihood ratio of H1 against H0, from the normal approximation to the trinomial
+    /// distribution of wins, draws and losses. Each count is regularised with a pseudo-count, so a
+    /// short one-sided streak isn't enough evidence to stop
+    pub fn llr(&self, result: &MatchResult) -> f64 {
+        if result.games() == 0 {
+            return 0.0;
+        }
+
+        let wins = result.wins as f64 + PSEUDO_COUNT;
+        let draws = result.draws as f64 + PSEUDO_COUNT;
+        let losses = result.losses as f64 + PSEUDO_COUNT;
+        let (score, variance) = moments(wins, draws, losses);
+
+        let score0 = expected_score(self.elo0);
+        let score1 = expected_score(self.elo1);
+
+        (wins + draws + losses) * (score1 - score0) * (2.0 * score - score0 - score1)
+            / (2.0 * variance)
+    }
+
+    pub fn status(&self, result: &MatchResult) -> SprtStatus {
+        let llr = self.llr(result);
+
+        if llr <= (self.beta / (1.0 - self.alpha)).ln() {
+            SprtStatus::AcceptH0
+        } else if llr >= ((1.0 - self.beta) / self.alpha).ln() {
+            SprtStatus::AcceptH1
+        } else {
+            SprtStatus::Continue
+        }
+    }
+}
+
+pub struct TournamentOptions {
+    /// The most games to play in each match
+    pub games: u64,
+    /// The number of random moves played from the start position before the engines take over.
+    /// Each opening is played twice, with the engines swapping sides
+    pub opening_moves: usize,
+    pub seed: u64,
+    /// Stop a match early once the test reaches a decision
+    pub sprt: Option<Sprt>,
+}
+
+impl Default for TournamentOptions {
+    fn default() -> Self {
+        TournamentOptions {
+            games: 100,
+            opening_moves: 2,
+            seed: 0,
+            sprt: None,
+        }
+    }
+}
+
+/// Plays a match between two engines from the start position, alternating which moves first
+pub fn play_match<T: MinimaxGame + Clone>(
+    start: &T,
+    first: &mut dyn Engine<T>,
+    second: &mut dyn Engine<T>,
+    options: &TournamentOptions,
+) -> MatchResult {
+    let mut rng = Rng::new(options.seed);
+    let mut result = MatchResult::default();
+    let mut opening = None;
+
+    for game in 0..options.games {
+        let first_moves_first = game % 2 == 0;
+        if first_moves_first {
+            opening = Some(random_opening(start, options.opening_moves, &mut rng));
+        }
+        let opening = opening.as_ref().expect("opening is chosen for even games");
+
+        let winner = if first_moves_first {
+            play_game(opening, first, second)
+        } else {
+            play_game(opening, second, first)
+        };
+
+        match winner {
+            None => result.draws += 1,
+            Some(winner) if (winner == opening.to_move()) == first_moves_first => result.wins += 1,
+            Some(_) => result.losses += 1,
+        }
+
+        // Only stop after complete pairs, so neither engine gets more games with the first move
+        if game % 2 == 1 {
+            if let Some(sprt) = options.sprt {
+                if sprt.status(&result) != SprtStatus::Continue {
+                    break;
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Plays a match between every pair of engines, returning the engine indices with the result
+/// from the point of view of the first
+pub fn round_robin<T: MinimaxGame + Clone>(
+    start: &T,
+    engines: &mut [Box<dyn Engine<T>>],
+    options: &TournamentOptions,
+) -> Vec<(usize, usize, MatchResult)> {
+    let mut results = vec![];
+
+    for i in 0..engines.len() {
+        for j in i + 1..engines.len() {
+            let (left, right) = engines.split_at_mut(j);
+            let result = play_match(start, left[i].as_mut(), right[0].as_mut(), options);
+
+            results.push((i, j, result));
+        }
+    }
+
+    results
+}
+
+/// Plays a game to the end, returning the winner
+fn play_game<T: MinimaxGame + Clone>(
+    start: &T,
+    first: &mut dyn Engine<T>,
+    second: &mut dyn Engine<T>,
+) -> Option<T::Player> {
+    let mut game = start.clone();
+    let player = game.to_move();
+
+    while !game.has_finished() {
+        let mv = if game.to_move() == player {
+            first.choose(&game)
+        } else {
+            second.choose(&game)
+        };
+
+        game = game.apply(mv);
+    }
+
+    game.winner()
+}
+
+/// The most random openings tried before giving up on finding one the engines can play from
+const OPENING_ATTEMPTS: usize = 100;
+
+/// Plays random moves from the start, retrying if the game finishes before the engines play. If
+/// every attempt finishes the game, the start position is used instead
+fn random_opening<T: MinimaxGame + Clone>(start: &T, moves: usize, rng: &mut Rng) -> T {
+    if start.has_finished() {
+        return start.clone();
+    }
+
+    for _ in 0..OPENING_ATTEMPTS {
+        let mut game = start.clone();
+
+        for _ in 0..moves {
+            if game.has_finished() {
+                break;
+            }
+
+            let legal: Vec<T::Move> = game.legal_moves().collect();
+            game = game.apply(legal[rng.below(legal.len())]);
+        }
+
+        if !game.has_finished() {
+            return game;
+        }
+    }
+
+    start.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{connect4::Game, game::mcts::MctsOptions};
+
+    use super::*;
+
+    #[test]
+    pub fn elo_from_score() {
+        assert_eq!(elo(0.5), 0.0);
+        assert!((elo(0.75) - 190.85).abs() < 0.01);
+        assert!((elo(0.25) + 190.85).abs() < 0.01);
+        assert_eq!(elo(1.0), f64::INFINITY);
+        assert!((expected_score(elo(0.64)) - 0.64).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn elo_interval_narrows_with_more_games() {
+        let few = MatchResult {
+            wins: 6,
+            draws: 2,
+            losses: 2,
+        };
+        let many = MatchResult {
+            wins: 600,
+            draws: 200,
+            losses: 200,
+        };
+
+        assert_eq!(few.elo(), many.elo());
+
+        let (few_low, few_high) = few.elo_interval();
+        let (many_low, many_high) = many.elo_interval();
+        assert!(few_low < many_low && many_low < many.elo());
+        assert!(many.elo() < many_high && many_high < few_high);
+    }
+
+    #[test]
+    pub fn sprt() {
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 20.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+
+        let even = MatchResult {
+            wins: 10,
+            draws: 10,
+            losses: 10,
+        };
+        assert_eq!(sprt.status(&even), SprtStatus::Continue);
+
+        let stronger = MatchResult {
+            wins: 1200,
+            draws: 600,
+            losses: 900,
+        };
+        assert!(sprt.llr(&stronger) > 0.0);
+        assert_eq!(sprt.status(&stronger), SprtStatus::AcceptH1);
+
+        let equal = MatchResult {
+            wins: 1000,
+            draws: 1000,
+            losses: 1000,
+        };
+        assert_eq!(sprt.status(&equal), SprtStatus::AcceptH0);
+    }
+
+    #[test]
+    pub fn sprt_one_sided() {
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 20.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+
+        let won = |wins| MatchResult {
+            wins,
+            draws: 0,
+            losses: 0,
+        };
+        assert_eq!(sprt.status(&won(2)), SprtStatus::Continue);
+        assert_eq!(sprt.status(&won(40)), SprtStatus::AcceptH1);
+
+        let lost = MatchResult {
+            wins: 0,
+            draws: 0,
+            losses: 40,
+        };
+        assert_eq!(sprt.status(&lost), SprtStatus::AcceptH0);
+    }
+
+    #[test]
+    pub fn no_games() {
+        let result = MatchResult::default();
+
+        assert_eq!(result.score(), 0.5);
+        assert_eq!(result.elo(), 0.0);
+        assert_eq!(result.elo_interval(), (f64::NEG_INFINITY, f64::INFINITY));
+        assert_eq!(result.to_string(), "+0 =0 -0, Elo 0.0 [-inf, inf]");
+    }
+
+    #[test]
+    pub fn sprt_rarely_stops_equal_match() {
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 20.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+
+        let result = |wins, losses| MatchResult {
+            wins,
+            draws: 0,
+            losses,
+        };
+        assert_eq!(sprt.status(&result(3, 0)), SprtStatus::Continue);
+        assert_eq!(sprt.status(&result(0, 3)), SprtStatus::Continue);
+
+        // The chance that evenly matched engines without draws wrongly accept H1 in their first
+        // 40 games, checking after every pair like play_match
+        let mut unfinished = vec![1.0];
+        let mut false_positive = 0.0;
+        for games in 1..=40 {
+            let mut next = vec![0.0; games + 1];
+            for (wins, chance) in unfinished.iter().enumerate() {
+                next[wins] += chance / 2.0;
+                next[wins + 1] += chance / 2.0;
+            }
+
+            if games % 2 == 0 {
+                for (wins, chance) in next.iter_mut().enumerate() {
+                    let status = sprt.status(&result(wins as u64, (games - wins) as u64));
+                    if status == SprtStatus::AcceptH1 {
+                        false_positive += *chance;
+                    }
+                    if status != SprtStatus::Continue {
+                        *chance = 0.0;
+                    }
+                }
+            }
+            unfinished = next;
+        }
+
+        assert!(false_positive < sprt.alpha, "{}", false_positive);
+    }
+
+    #[test]
+    pub fn random_opening_gives_up() {
+        let mut rng = Rng::new(0);
+        let start = crate::tictactoe::Game::new();
+
+        let opening = random_opening(&start, 2, &mut rng);
+        assert_eq!(opening.legal_moves().count(), 7);
+
+        // Every game of Tic-Tac-Toe is over within nine moves
+        assert_eq!(random_opening(&start, 9, &mut rng), start);
+
+        let finished = [0, 3, 1, 4, 2]
+            .iter()
+            .fold(start.clone(), |game, space| game.add_piece(*space));
+        assert!(finished.has_won());
+        assert_eq!(random_opening(&finished, 2, &mut rng), finished);
+    }
+
+    #[test]
+    pub fn alpha_beta_beats_random() {
+        let options = TournamentOptions {
+            games: 10,
+            ..Default::default()
+        };
+        let result = play_match(
+            &Game::new(),
+            &mut AlphaBetaEngine { depth: 4 },
+            &mut RandomEngine::new(1),
+            &options,
+        );
+
+        assert_eq!(result.games(), 10);
+        assert!(result.wins >= 9, "{}", result);
+        assert!(result.elo() > 0.0);
+    }
+
+    #[test]
+    pub fn sprt_stops_early() {
+        let options = TournamentOptions {
+            games: 1000,
+            sprt: Some(Sprt {
+                elo0: 0.0,
+                elo1: 100.0,
+                alpha: 0.05,
+                beta: 0.05,
+            }),
+            ..Default::default()
+        };
+        let result = play_match(
+            &Game::new(),
+            &mut AlphaBetaEngine { depth: 2 },
+            &mut RandomEngine::new(1),
+            &options,
+        );
+
+        assert!(result.games() < 1000);
+        assert_eq!(result.games() % 2, 0);
+        assert_eq!(options.sprt.unwrap().status(&result), SprtStatus::AcceptH1);
+    }
+
+    #[test]
+    pub fn round_robin_plays_every_pair() {
+        let mut engines: Vec<Box<dyn Engine<Game>>> = vec![
+            Box::new(MinimaxEngine { depth: 1 }),
+            Box::new(AlphaBetaEngine { depth: 2 }),
+            Box::new(MctsEngine::new(MctsOptions {
+                iterations: Some(50),
+                ..Default::default()
+            })),
+            Box::new(RandomEngine::new(0)),
+        ];
+        let options = TournamentOptions {
+            games: 2,
+            ..Default::default()
+        };
+
+        let results = round_robin(&Game::new(), &mut engines, &options);
+
+        assert_eq!(results.len(), 6);
+        assert_eq!(results[0].0, 0);
+        assert_eq!(results[0].1, 1);
+        assert!(results.iter().all(|(_, _, result)| result.games() == 2));
+        assert_eq!(engines[2].name(), "mcts(50)");
+    }
+}
@@ -0,0 +1,183 @@
+//! A fixed size transposition table, caching search results by Zobrist key
+
+/// How a stored value relates to the true minimax value of the position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// The value is exact
+    Exact,
+    /// The search failed high, so the true value is at least this
+    Lower,
+    /// The search failed low, so the true value is at most this
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry<M> {
+    pub key: u64,
+    pub depth: usize,
+    pub value: i64,
+    pub bound: Bound,
+    pub best_move: Option<M>,
+}
+
+/// Decides whether a new entry may overwrite the one already in its slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replacement {
+    /// The newest entry always wins
+    Always,
+    /// Entries from earlier searches are always replaced, but within a search deeper entries are kept
+    DepthPreferred,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot<M> {
+    entry: Entry<M>,
+    generation: u32,
+}
+
+pub struct TranspositionTable<M> {
+    slots: Vec<Option<Slot<M>>>,
+    replacement: Replacement,
+    generation: u32,
+    probes: u64,
+    hits: u64,
+}
+
+impl<M: Copy> TranspositionTable<M> {
+    /// Creates a depth preferred table with at least `size` slots, rounded up to a power of two
+    pub fn new(size: usize) -> Self {
+        Self::with_replacement(size, Replacement::DepthPreferred)
+    }
+
+    pub fn with_replacement(size: usize, replacement: Replacement) -> Self {
+        TranspositionTable {
+            slots: vec![None; size.max(1).next_power_of_two()],
+            replacement,
+            generation: 0,
+            probes: 0,
+            hits: 0,
+        }
+    }
+
+    /// Returns the entry stored for this key, if there is one
+    pub fn probe(&mut self, key: u64) -> Option<Entry<M>> {
+        self.probes += 1;
+
+        match self.slots[self.index(key)] {
+            Some(slot) if slot.entry.key == key => {
+                self.hits += 1;
+                Some(slot.entry)
+            }
+            _ => None,
+        }
+    }
+
+    /// Stores an entry, subject to the replacement policy
+    pub fn store(&mut self, entry: Entry<M>) {
+        let index = self.index(entry.key);
+
+        let replace = match (self.replacement, &self.slots[index]) {
+            (Replacement::Always, _) | (_, None) => true,
+            (Replacement::DepthPreferred, Some(slot)) => {
+                slot.generation != self.generation
+                    || slot.entry.key == entry.key
+                    || slot.entry.depth <= entry.depth
+            }
+        };
+
+        if replace {
+            self.slots[index] = Some(Slot {
+                entry,
+                generation: self.generation,
+            });
+        }
+    }
+
+    /// Marks every stored entry as belonging to an earlier search, so they can be replaced freely
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+        self.probes = 0;
+        self.hits = 0;
+    }
+
+    /// The number of slots in the table
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn probes(&self) -> u64 {
+        self.probes
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    fn index(&self, key: u64) -> usize {
+        // The capacity is a power of two, so masking picks the low bits of the key
+        (key as usize) & (self.slots.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bound, Entry, Replacement, TranspositionTable};
+
+    fn entry(key: u64, depth: usize, value: i64) -> Entry<usize> {
+        Entry {
+            key,
+            depth,
+            value,
+            bound: Bound::Exact,
+            best_move: None,
+        }
+    }
+
+    #[test]
+    pub fn capacity_rounds_up() {
+        assert_eq!(TranspositionTable::<usize>::new(1000).capacity(), 1024);
+        assert_eq!(TranspositionTable::<usize>::new(0).capacity(), 1);
+    }
+
+    #[test]
+    pub fn probe() {
+        let mut table = TranspositionTable::new(16);
+        table.store(entry(3, 2, 10));
+
+        assert_eq!(table.probe(3).map(|e| e.value), Some(10));
+        assert!(table.probe(4).is_none());
+
+        // Same slot, different key
+        assert!(table.probe(19).is_none());
+        assert_eq!(table.probes(), 3);
+        assert_eq!(table.hits(), 1);
+    }
+
+    #[test]
+    pub fn depth_preferred() {
+        let mut table = TranspositionTable::new(16);
+        table.store(entry(3, 5, 10));
+        table.store(entry(19, 2, 20));
+
+        assert_eq!(table.probe(3).map(|e| e.value), Some(10));
+
+        // Entries from an older search can always be replaced
+        table.new_search();
+        table.store(entry(19, 2, 20));
+        assert_eq!(table.probe(19).map(|e| e.value), Some(20));
+    }
+
+    #[test]
+    pub fn always_replace() {
+        let mut table = TranspositionTable::with_replacement(16, Replacement::Always);
+        table.store(entry(3, 5, 10));
+        table.store(entry(19, 2, 20));
+
+        assert!(table.probe(3).is_none());
+        assert_eq!(table.probe(19).map(|e| e.value), Some(20));
+    }
+}
@@ -0,0 +1,307 @@
+//! Records the tree a search explores, to draw with Graphviz or read as JSON. Each node keeps the
+//! window it was searched with and the value it got, and the moves a cutoff skipped are kept as
+//! pruned branches, so the trees of different algorithms and move orderings can be compared
+
+use std::fmt::{Debug, Write};
+
+/// A position the search visited. The window and value are from the point of view of the player to
+/// move there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeNode<M> {
+    /// The move leading here. It is None at the root, and for each pass MTD(f) makes over a
+    /// position, which becomes a child of the position's node
+    pub mv: Option<M>,
+    /// The number of moves from the root
+    pub ply: usize,
+    /// The search window. Minimax has no window, so its nodes have the full one
+    pub alpha: i64,
+    pub beta: i64,
+    /// None if the search was aborted before the value was found
+    pub value: Option<i64>,
+    /// True if the value came from the transposition table
+    pub table_hit: bool,
+    /// The indices of the nodes searched from here, in order
+    pub children: Vec<usize>,
+    /// The moves a cutoff stopped the search from trying
+    pub pruned: Vec<M>,
+}
+
+/// The tree of one search, or of the last completed iteration of iterative deepening. Nodes more
+/// than `max_ply` moves from the root are searched as usual but not recorded
+#[derive(Debug, Clone)]
+pub struct SearchTree<M> {
+    nodes: Vec<TreeNode<M>>,
+    max_ply: usize,
+    /// The nodes being searched, from the root down, with None for those too deep to record
+    path: Vec<Option<usize>>,
+}
+
+impl<M: Copy + Debug> SearchTree<M> {
+    pub fn new(max_ply: usize) -> Self {
+        SearchTree {
+            nodes: vec![],
+            max_ply,
+            path: vec![],
+        }
+    }
+
+    pub fn max_ply(&self) -> usize {
+        self.max_ply
+    }
+
+    /// Every recorded node, with the root first and each node before its children
+    pub fn nodes(&self) -> &[TreeNode<M>] {
+        &self.nodes
+    }
+
+    pub fn root(&self) -> Option<&TreeNode<M>> {
+        self.nodes.first()
+    }
+
+    /// The number of moves cut off across the whole tree
+    pub fn pruned(&self) -> usize {
+        self.nodes.iter().map(|node| node.pruned.len()).sum()
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.nodes.clear();
+        self.path.clear();
+    }
+
+    /// Starts a node below the one being searched, or the root if there isn't one
+    pub(super) fn enter(&mut self, mv: Option<M>, alpha: i64, beta: i64) {
+        let (parent, ply) = match self.path.last() {
+            None => (None, 0),
+            Some(Some(parent)) => (
+                Some(*parent),
+                self.nodes[*parent].ply + mv.is_some() as usize,
+            ),
+            Some(None) => {
+                self.path.push(None);
+                return;
+            }
+        };
+
+        if ply > self.max_ply {
+            self.path.push(None);
+            return;
+        }
+
+        let index = self.nodes.len();
+        self.nodes.push(TreeNode {
+            mv,
+            ply,
+            alpha,
+            beta,
+            value: None,
+            table_hit: false,
+            children: vec![],
+            pruned: vec![],
+        });
+
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(index);
+        }
+        self.path.push(Some(index));
+    }
+
+    /// Finishes the node being searched
+    pub(super) fn exit(&mut self, value: Option<i64>) {
+        if let Some(Some(index)) = self.path.pop() {
+            self.nodes[index].value = value;
+        }
+    }
+
+    pub(super) fn prune(&mut self, moves: &[M]) {
+        if let Some(node) = self.current() {
+            node.pruned.extend_from_slice(moves);
+        }
+    }
+
+    pub(super) fn table_hit(&mut self) {
+        if let Some(node) = self.current() {
+            node.table_hit = true;
+        }
+    }
+
+    fn current(&mut self) -> Option<&mut TreeNode<M>> {
+        let index = (*self.path.last()?)?;
+        self.nodes.get_mut(index)
+    }
+
+    /// Writes the tree in Graphviz's DOT language. Pruned moves are drawn dashed and grey, and
+    /// values from the transposition table in blue
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph search {\n    node [shape=box];\n");
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            let value = node.value.map_or("?".to_string(), bound);
+            let colour = if node.table_hit { ", color=blue" } else { "" };
+            writeln!(
+                dot,
+                "    n{} [label=\"{}\\n[{}, {}]\"{}];",
+                index,
+                value,
+                bound(node.alpha),
+                bound(node.beta),
+                colour
+            )
+            .expect("writing to a string can't fail");
+
+            for child in node.children.iter() {
+                let label = self.nodes[*child].mv.map_or(String::new(), label);
+                writeln!(dot, "    n{} -> n{} [label=\"{}\"];", index, child, label)
+                    .expect("writing to a string can't fail");
+            }
+
+            for (i, mv) in node.pruned.iter().enumerate() {
+                writeln!(
+                    dot,
+                    "    p{}_{} [label=\"pruned\", style=dashed, color=grey];\n    \
+                     n{} -> p{}_{} [label=\"{}\", style=dashed, color=grey];",
+                    index,
+                    i,
+                    index,
+                    index,
+                    i,
+                    label(*mv)
+                )
+                .expect("writing to a string can't fail");
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Writes the tree as nested JSON objects, starting from the root. Moves are written as
+    /// strings in their debug format, and a value is null if the search was aborted before
+    /// finding it
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        if self.nodes.is_empty() {
+            json.push_str("null");
+        } else {
+            self.write_json(&mut json, 0);
+        }
+
+        json
+    }
+
+    fn write_json(&self, json: &mut String, index: usize) {
+        let node = &self.nodes[index];
+        let mv = node
+            .mv
+            .map_or("null".to_string(), |mv| format!("\"{}\"", label(mv)));
+        let value = node
+            .value
+            .map_or("null".to_string(), |value| value.to_string());
+        let pruned: Vec<String> = node
+            .pruned
+            .iter()
+            .map(|mv| format!("\"{}\"", label(*mv)))
+            .collect();
+
+        write!(
+            json,
+            "{{\"move\":{},\"ply\":{},\"alpha\":{},\"beta\":{},\"value\":{},\"table_hit\":{},\
+             \"pruned\":[{}],\"children\":[",
+            mv,
+            node.ply,
+            node.alpha,
+            node.beta,
+            value,
+            node.table_hit,
+            pruned.join(",")
+        )
+        .expect("writing to a string can't fail");
+
+        for (i, child) in node.children.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            self.write_json(json, *child);
+        }
+
+        json.push_str("]}");
+    }
+}
+
+/// A move in its debug format, escaped to go inside quotes in either format
+fn label<M: Debug>(mv: M) -> String {
+    format!("{:?}", mv)
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+}
+
+/// A window bound or value, with the limits the search uses for infinity written as such
+fn bound(value: i64) -> String {
+    match value {
+        i64::MAX => "inf".to_string(),
+        value if value == i64::MIN + 1 => "-inf".to_string(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SearchTree;
+
+    /// A root with one move searched, which cut off the second, and one move below the ply limit
+    fn small_tree() -> SearchTree<usize> {
+        let mut tree = SearchTree::new(1);
+
+        tree.enter(None, i64::MIN + 1, i64::MAX);
+        tree.enter(Some(0), -i64::MAX, -3);
+        tree.enter(Some(5), 3, i64::MAX);
+        tree.exit(Some(4));
+        tree.prune(&[6]);
+        tree.exit(Some(-4));
+        tree.exit(Some(4));
+
+        tree
+    }
+
+    #[test]
+    pub fn records_nodes() {
+        let tree = small_tree();
+
+        assert_eq!(tree.nodes().len(), 2);
+        assert_eq!(tree.root().unwrap().children, vec![1]);
+        assert_eq!(tree.nodes()[1].ply, 1);
+        assert_eq!(tree.nodes()[1].pruned, vec![6]);
+        assert_eq!(tree.pruned(), 1);
+    }
+
+    #[test]
+    pub fn dot() {
+        assert_eq!(
+            small_tree().to_dot(),
+            "digraph search {\n    \
+             node [shape=box];\n    \
+             n0 [label=\"4\\n[-inf, inf]\"];\n    \
+             n0 -> n1 [label=\"0\"];\n    \
+             n1 [label=\"-4\\n[-inf, -3]\"];\n    \
+             p1_0 [label=\"pruned\", style=dashed, color=grey];\n    \
+             n1 -> p1_0 [label=\"6\", style=dashed, color=grey];\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    pub fn json() {
+        assert_eq!(
+            small_tree().to_json(),
+            format!(
+                "{{\"move\":null,\"ply\":0,\"alpha\":{},\"beta\":{},\"value\":4,\
+                 \"table_hit\":false,\"pruned\":[],\"children\":[\
+                 {{\"move\":\"0\",\"ply\":1,\"alpha\":{},\"beta\":-3,\"value\":-4,\
+                 \"table_hit\":false,\"pruned\":[\"6\"],\"children\":[]}}]}}",
+                i64::MIN + 1,
+                i64::MAX,
+                -i64::MAX
+            )
+        );
+        assert_eq!(SearchTree::<usize>::new(3).to_json(), "null");
+    }
+}
@@ -1,21 +1,384 @@
-use std::path::Path;
+use std::{
+    env,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+    process,
+};
 
+use connect4::{Color, Game};
+use connect4_cli::PlayOptions;
+use connect4_eval::EvalConfig;
+use connect4_solver::{Book, Solver};
+use connect4_training::TrainingOptions;
+use game::{
+    search::{Algorithm, Searcher},
+    MinimaxGame,
+};
 use senate::DisorderTree;
 
 pub mod algrebra;
 pub mod connect4;
+pub mod connect4_cli;
+pub mod connect4_eval;
+pub mod connect4_history;
+pub mod connect4_solver;
+pub mod connect4_training;
 pub mod csp;
 pub mod game;
 pub mod graph;
+pub mod mnk;
+pub mod nim;
 pub mod nqueens;
 pub mod nqueens_csp;
+pub mod othello;
+pub mod random;
 pub mod senate;
 pub mod soduku;
+pub mod tictactoe;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("connect4") => run_connect4(&args[1..]),
+        Some("nqueens") => run_nqueens(&args[1..]),
+        Some("solve") => run_solve(&args[1..]),
+        Some("train") => run_train(&args[1..]),
+        Some("tree") => run_tree(&args[1..]),
+        _ => run_senate(),
+    }
+}
+
+fn run_senate() {
     let data = senate::parse(Path::new("data/S110.ord"));
     let bills = senate::parse_bills(Path::new("data/S110desc.csv"));
 
     let tree = DisorderTree::new(data.iter().map(|l| l).collect());
     tree.print(&bills, 0);
 }
+
+/// Plays Connect Four against the computer, with the color given by --color and the search depth
+/// by --depth. The computer evaluates positions with the weights in the file given by --weights if
+/// there is one. With --record the game is written to a file at the end
+fn run_connect4(args: &[String]) {
+    let mut options = PlayOptions::default();
+    let mut record_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--color" => {
+                options.human = match args.next().map(String::as_str) {
+                    Some("white") => Color::White,
+                    Some("black") => Color::Black,
+                    _ => usage_error("--color expects white or black"),
+                }
+            }
+            "--depth" => {
+                options.depth = match args.next().map(|value| value.parse()) {
+                    Some(Ok(value)) if value > 0 => value,
+                    _ => usage_error("--depth expects a positive number of moves"),
+                }
+            }
+            "--record" => match args.next() {
+                Some(value) => record_path = Some(value.clone()),
+                None => usage_error("--record expects a file"),
+            },
+            "--weights" => match args.next() {
+                Some(path) => options.eval = load_weights(path),
+                None => usage_error("--weights expects a file"),
+            },
+            other => usage_error(format!("unknown argument {}", other)),
+        }
+    }
+
+    let stdin = io::stdin();
+    let history = match connect4_cli::play(stdin.lock(), &mut io::stdout(), &options) {
+        Ok(history) => history,
+        Err(error) => fail(error),
+    };
+
+    if let Some(path) = record_path {
+        if let Err(error) = fs::write(&path, history.record().to_string()) {
+            fail(error);
+        }
+    }
+}
+
+/// Solves the position given with --moves, using the opening book given with --book if there is
+/// one. With --generate-book N, solves every position up to N moves after it and saves them to the
+/// book instead. --table sets the number of transposition table entries
+fn run_solve(args: &[String]) {
+    let mut moves = String::new();
+    let mut book_path = None;
+    let mut generate = None;
+    let mut table_size = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--moves" => match args.next() {
+                Some(value) => moves = value.clone(),
+                None => usage_error("--moves expects a sequence of columns such as 4453"),
+            },
+            "--book" => match args.next() {
+                Some(value) => book_path = Some(value.clone()),
+                None => usage_error("--book expects a file"),
+            },
+            "--generate-book" => match args.next().map(|value| value.parse()) {
+                Some(Ok(value)) => generate = Some(value),
+                _ => usage_error("--generate-book expects a number of moves"),
+            },
+            "--table" => match args.next().map(|value| value.parse()) {
+                Some(Ok(value)) => table_size = Some(value),
+                _ => usage_error("--table expects a number of entries"),
+            },
+            other => usage_error(format!("unknown argument {}", other)),
+        }
+    }
+
+    let game = match Game::from_moves(&moves) {
+        Ok(game) => game,
+        Err(error) => usage_error(error),
+    };
+
+    let mut solver = table_size.map_or_else(Solver::new, Solver::with_table_size);
+
+    if let Some(depth) = generate {
+        let Some(path) = book_path else {
+            usage_error("--generate-book needs --book to say where to save it")
+        };
+
+        let book = Book::generate(&mut solver, &game, depth);
+        let saved = File::create(&path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            book.save(&mut writer)?;
+            writer.flush()
+        });
+        match saved {
+            Ok(()) => println!("Saved {} positions to {}", book.len(), path),
+            Err(error) => fail(error),
+        }
+        return;
+    }
+
+    if let Some(path) = book_path {
+        match File::open(&path).and_then(|file| Book::load(BufReader::new(file))) {
+            Ok(book) => solver = solver.with_book(book),
+            Err(error) => fail(error),
+        }
+    }
+
+    print!("{}", game);
+    if game.has_won() || game.has_tied() {
+        println!("The game is over");
+        return;
+    }
+
+    let solution = solver.solve(&game);
+    println!(
+        "Score {}: {:?} in {} moves",
+        solution.score,
+        solution.outcome(),
+        solution.plies_to_end()
+    );
+
+    let scores: Vec<String> = solver
+        .analyse(&game)
+        .iter()
+        .map(|score| score.map_or("-".to_string(), |score| score.to_string()))
+        .collect();
+    println!("Columns {}", scores.join(" "));
+}
+
+/// Learns evaluation weights from --games self-play games searched to --depth, starting from the
+/// weights given with --weights or the defaults, and saves them to the file given by --output
+fn run_train(args: &[String]) {
+    let mut options = TrainingOptions::default();
+    let mut eval = EvalConfig::default();
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--games" => match args.next().map(|value| value.parse()) {
+                Some(Ok(value)) => options.games = value,
+                _ => usage_error("--games expects a number of games"),
+            },
+            "--depth" => match args.next().map(|value| value.parse()) {
+                Some(Ok(value)) if value > 0 => options.depth = value,
+                _ => usage_error("--depth expects a positive number of moves"),
+            },
+            "--weights" => match args.next() {
+                Some(path) => eval = load_weights(path),
+                None => usage_error("--weights expects a file"),
+            },
+            "--output" => match args.next() {
+                Some(value) => output = Some(value.clone()),
+                None => usage_error("--output expects a file"),
+            },
+            other => usage_error(format!("unknown argument {}", other)),
+        }
+    }
+
+    let Some(output) = output else {
+        usage_error("--output is needed to say where to save the weights")
+    };
+
+    let samples = connect4_training::self_play(eval, &options);
+    let trained = connect4_training::fit(eval, &samples, &options);
+    println!(
+        "Log loss over {} positions went from {:.4} to {:.4}",
+        samples.len(),
+        connect4_training::log_loss(&eval, &samples),
+        connect4_training::log_loss(&trained, &samples)
+    );
+
+    let saved = File::create(&output).and_then(|file| {
+        // Dropping the writer would ignore errors from its last write, so flush it here
+        let mut writer = BufWriter::new(file);
+        trained.save(&mut writer)?;
+        writer.flush()
+    });
+    match saved {
+        Ok(()) => println!("Saved {:?} to {}", trained, output),
+        Err(error) => fail(error),
+    }
+}
+
+/// Searches the tic-tac-toe position reached by the spaces given with --moves, numbered 0 to 8
+/// along each row from the top, and writes the tree explored down to --ply moves as Graphviz DOT,
+/// or as JSON with --format json. The search runs to --depth with the --algorithm given: minimax,
+/// alphabeta, failsoft, negascout or mtdf. The tree goes to the file given by --output if there
+/// is one
+fn run_tree(args: &[String]) {
+    let mut game = tictactoe::Game::new();
+    let mut algorithm = Algorithm::AlphaBeta;
+    let mut depth = 9;
+    let mut max_ply = 2;
+    let mut json = false;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--moves" => {
+                let Some(moves) = args.next() else {
+                    usage_error("--moves expects a sequence of spaces such as 403")
+                };
+
+                for c in moves.chars() {
+                    match c.to_digit(10).map(|space| space as usize) {
+                        Some(space) if game.legal_moves().any(|mv| mv == space) => {
+                            game = game.apply(space)
+                        }
+                        _ => usage_error(format!("{} is not a legal move", c)),
+                    }
+                }
+            }
+            "--algorithm" => {
+                algorithm = match args.next().map(String::as_str) {
+                    Some("minimax") => Algorithm::Minimax,
+                    Some("alphabeta") => Algorithm::AlphaBeta,
+                    Some("failsoft") => Algorithm::FailSoft,
+                    Some("negascout") => Algorithm::Negascout,
+                    Some("mtdf") => Algorithm::MtdF,
+                    _ => usage_error(
+                        "--algorithm expects minimax, alphabeta, failsoft, negascout or mtdf",
+                    ),
+                }
+            }
+            "--depth" => match args.next().map(|value| value.parse()) {
+                Some(Ok(value)) if value > 0 => depth = value,
+                _ => usage_error("--depth expects a positive number of moves"),
+            },
+            "--ply" => match args.next().map(|value| value.parse()) {
+                Some(Ok(value)) => max_ply = value,
+                _ => usage_error("--ply expects a number of moves"),
+            },
+            "--format" => {
+                json = match args.next().map(String::as_str) {
+                    Some("dot") => false,
+                    Some("json") => true,
+                    _ => usage_error("--format expects dot or json"),
+                }
+            }
+            "--output" => match args.next() {
+                Some(value) => output = Some(value.clone()),
+                None => usage_error("--output expects a file"),
+            },
+            other => usage_error(format!("unknown argument {}", other)),
+        }
+    }
+
+    if game.has_finished() {
+        usage_error("The game is over")
+    }
+
+    let mut searcher = Searcher::new(algorithm).with_tree(max_ply);
+    let result = searcher.search(&game, depth);
+    let tree = searcher.tree().expect("the tree is being recorded");
+    let text = if json { tree.to_json() } else { tree.to_dot() };
+
+    match output {
+        Some(path) => match fs::write(&path, text) {
+            Ok(()) => println!("{}\nSaved {} nodes to {}", result, tree.nodes().len(), path),
+            Err(error) => fail(error),
+        },
+        None => println!("{}", text),
+    }
+}
+
+/// Reads evaluation weights saved by training, exiting with why if they can't be read
+fn load_weights(path: &str) -> EvalConfig {
+    match File::open(path).and_then(|file| EvalConfig::load(BufReader::new(file))) {
+        Ok(eval) => eval,
+        Err(error) => fail(format!("{}: {}", path, error)),
+    }
+}
+
+/// Exits with status 2 after explaining what was wrong with the arguments, so scripts can tell
+fn usage_error(message: impl Display) -> ! {
+    eprintln!("{}", message);
+    process::exit(2)
+}
+
+/// Exits with status 1 after saying why the command failed
+fn fail(message: impl Display) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
+
+/// Prints every solution for the board size given with --n, or just the number of them with --count
+fn run_nqueens(args: &[String]) {
+    let mut n = 8;
+    let mut count_only = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--n" => {
+                n = match args.next().map(|value| value.parse()) {
+                    Some(Ok(value)) if value <= nqueens::MAX_BITBOARD_SIZE => value,
+                    _ => usage_error(format!(
+                        "--n expects a board size of at most {}",
+                        nqueens::MAX_BITBOARD_SIZE
+                    )),
+                }
+            }
+            "--count" => count_only = true,
+            other => usage_error(format!("unknown argument {}", other)),
+        }
+    }
+
+    if count_only {
+        println!("{}", nqueens::count_solutions_parallel(n));
+        return;
+    }
+
+    for (i, board) in nqueens::solutions(n).enumerate() {
+        println!("Solution {}\n{}", i + 1, board);
+    }
+}
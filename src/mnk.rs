@@ -0,0 +1,449 @@
+//! m,n,k games, where players take turns to place pieces on an m by n board and the first to get
+//! k in a row wins. With gravity pieces drop to the bottom of their column, as in Connect Four,
+//! and without it they can go anywhere, as in gomoku
+
+use std::{fmt::Display, ops::Index, str::FromStr};
+
+use crate::{
+    connect4::Color,
+    game::{MinimaxGame, WIN},
+    random,
+};
+
+const ZOBRIST_SEED: u64 = 0x6d6e_6b00;
+
+/// The directions a line can run in: right, down, down right and up right
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rules {
+    pub width: usize,
+    pub height: usize,
+    /// The length of line needed to win
+    pub k: usize,
+    /// Whether pieces fall to the lowest empty space in their column
+    pub gravity: bool,
+}
+
+impl Rules {
+    /// Pieces are dropped into columns, like Connect Four
+    pub fn connect(width: usize, height: usize, k: usize) -> Self {
+        Rules {
+            width,
+            height,
+            k,
+            gravity: true,
+        }
+    }
+
+    /// Pieces can be placed in any empty space, like gomoku
+    pub fn free(width: usize, height: usize, k: usize) -> Self {
+        Rules {
+            width,
+            height,
+            k,
+            gravity: false,
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::connect(7, 6, 4)
+    }
+}
+
+impl FromStr for Rules {
+    type Err = String;
+
+    /// Parses "width,height,k", with ",free" on the end to turn off gravity
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+
+        let gravity = match parts.get(3) {
+            None => true,
+            Some(&"free") => false,
+            Some(other) => return Err(format!("unknown rule \"{}\"", other)),
+        };
+
+        if parts.len() < 3 || parts.len() > 4 {
+            return Err(format!("expected width,height,k but got \"{}\"", s));
+        }
+
+        let mut numbers = [0; 3];
+        for (number, part) in numbers.iter_mut().zip(parts.iter()) {
+            *number = match part.parse() {
+                Ok(value) if value > 0 => value,
+                _ => return Err(format!("\"{}\" is not a positive number", part)),
+            };
+        }
+
+        let [width, height, k] = numbers;
+        if k > width.max(height) {
+            return Err(format!("a line of {} doesn't fit on the board", k));
+        }
+
+        Ok(Rules {
+            width,
+            height,
+            k,
+            gravity,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    rules: Rules,
+    /// The spaces row by row, starting from the top
+    board: Vec<Option<Color>>,
+    /// The number of pieces in each column
+    heights: Vec<usize>,
+    current_player: Color,
+    last_placement: Option<(usize, usize)>,
+    moves: usize,
+    hash: u64,
+}
+
+impl Index<(usize, usize)> for Game {
+    type Output = Option<Color>;
+
+    /// Returns the piece in column x, row y, where row 0 is the top of the board
+    fn index(&self, index: (usize, usize)) -> &Self::Output {
+        let (x, y) = index;
+        if x >= self.rules.width || y >= self.rules.height {
+            &None
+        } else {
+            &self.board[y * self.rules.width + x]
+        }
+    }
+}
+
+impl MinimaxGame for Game {
+    /// A column with gravity, otherwise the index of a space counting along each row from the top
+    type Move = usize;
+    type Moves = std::vec::IntoIter<usize>;
+    type Player = Color;
+
+    fn to_move(&self) -> Color {
+        self.current_player
+    }
+
+    /// Counts the lines of k spaces each player could still complete, weighting each by the
+    /// square of the pieces already in it
+    fn evaluate(&self, depth: usize) -> i64 {
+        if self.has_won() {
+            return -WIN - depth as i64; // Prefer earlier victories
+        }
+
+        let mut score = 0;
+        for (x, y) in self.spaces() {
+            for direction in DIRECTIONS {
+                let Some(window) = self.window(x, y, direction) else {
+                    continue;
+                };
+
+                let ours = window
+                    .iter()
+                    .filter(|space| **space == Some(self.current_player))
+                    .count() as i64;
+                let theirs = window.iter().filter(|space| space.is_some()).count() as i64 - ours;
+
+                if theirs == 0 {
+                    score += ours * ours;
+                } else if ours == 0 {
+                    score -= theirs * theirs;
+                }
+            }
+        }
+
+        score
+    }
+
+    fn has_finished(&self) -> bool {
+        self.has_won() || self.has_tied()
+    }
+
+    fn winner(&self) -> Option<Color> {
+        // Only the player who just moved can have won
+        self.has_won().then_some(self.current_player.opponent())
+    }
+
+    fn legal_moves(&self) -> Self::Moves {
+        let moves: Vec<usize> = if self.rules.gravity {
+            (0..self.rules.width)
+                .filter(|column| self.heights[*column] < self.rules.height)
+                .collect()
+        } else {
+            (0..self.board.len())
+                .filter(|space| self.board[*space].is_none())
+                .collect()
+        };
+
+        moves.into_iter()
+    }
+
+    fn apply(&self, mv: usize) -> Self {
+        self.add_piece(mv)
+    }
+
+    fn zobrist_key(&self) -> u64 {
+        self.hash
+    }
+
+    /// Spaces near the centre take part in more lines, so search them first
+    fn order_moves(&self, moves: &mut [usize]) {
+        moves.sort_by_key(|mv| {
+            let (x, y) = self.placement(*mv);
+            let distance = x.abs_diff(self.rules.width / 2)
+                + if self.rules.gravity {
+                    0
+                } else {
+                    y.abs_diff(self.rules.height / 2)
+                };
+
+            (distance, *mv)
+        });
+    }
+}
+
+impl Game {
+    pub fn new(rules: Rules) -> Self {
+        Game {
+            rules,
+            board: vec![None; rules.width * rules.height],
+            heights: vec![0; rules.width],
+            current_player: Color::White,
+            last_placement: None,
+            moves: 0,
+            hash: 0,
+        }
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Places a piece for the player to move, which must be a legal move
+    pub fn add_piece(&self, mv: usize) -> Self {
+        let (x, y) = self.placement(mv);
+        let mut new_board = self.clone();
+
+        new_board.board[y * self.rules.width + x] = Some(self.current_player);
+        new_board.heights[x] += 1;
+        new_board.last_placement = Some((x, y));
+        new_board.moves += 1;
+        new_board.hash ^= self.zobrist_key_for(self.current_player, x, y);
+        new_board.current_player = self.current_player.opponent();
+
+        new_board
+    }
+
+    /// Returns true if every space is full
+    pub fn has_tied(&self) -> bool {
+        self.moves == self.board.len()
+    }
+
+    /// Returns true if the last piece played won
+    pub fn has_won(&self) -> bool {
+        let Some((x, y)) = self.last_placement else {
+            return false;
+        };
+
+        DIRECTIONS.iter().any(|(dx, dy)| {
+            1 + self.line_length(x, y, *dx, *dy) + self.line_length(x, y, -dx, -dy) >= self.rules.k
+        })
+    }
+
+    /// The space a move puts a piece in
+    fn placement(&self, mv: usize) -> (usize, usize) {
+        if self.rules.gravity {
+            (mv, self.rules.height - 1 - self.heights[mv])
+        } else {
+            (mv % self.rules.width, mv / self.rules.width)
+        }
+    }
+
+    fn spaces(&self) -> impl Iterator<Item = (usize, usize)> {
+        let width = self.rules.width;
+        (0..self.board.len()).map(move |space| (space % width, space / width))
+    }
+
+    /// Counts the pieces matching the one in (x, y) in a line from it, not including itself
+    fn line_length(&self, x: usize, y: usize, dx: isize, dy: isize) -> usize {
+        let color = self[(x, y)];
+
+        (1..self.rules.k)
+            .map_while(|step| self.offset(x, y, dx * step as isize, dy * step as isize))
+            .take_while(|space| self[*space] == color)
+            .count()
+    }
+
+    /// The k spaces in a line from (x, y), if they all fit on the board
+    fn window(&self, x: usize, y: usize, direction: (isize, isize)) -> Option<Vec<Option<Color>>> {
+        (0..self.rules.k as isize)
+            .map(|step| {
+                self.offset(x, y, direction.0 * step, direction.1 * step)
+                    .map(|space| self[space])
+            })
+            .collect()
+    }
+
+    fn offset(&self, x: usize, y: usize, dx: isize, dy: isize) -> Option<(usize, usize)> {
+        let x = x.checked_add_signed(dx)?;
+        let y = y.checked_add_signed(dy)?;
+
+        (x < self.rules.width && y < self.rules.height).then_some((x, y))
+    }
+
+    /// Keys are derived from the space rather than stored, as the board size is only known at
+    /// run time
+    fn zobrist_key_for(&self, color: Color, x: usize, y: usize) -> u64 {
+        let offset = match color {
+            Color::White => 0,
+            Color::Black => self.board.len(),
+        };
+
+        random::split_mix(ZOBRIST_SEED + (offset + y * self.rules.width + x) as u64).1
+    }
+}
+
+impl Display for Game {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = (self.rules.height - 1).to_string().len();
+
+        // Write the board header, using the last digit of each column
+        write!(f, "{:label$} ", "")?;
+        for x in 0..self.rules.width {
+            write!(f, "{}", x % 10)?;
+            if x + 1 < self.rules.width {
+                write!(f, " ")?;
+            }
+        }
+        writeln!(f)?;
+
+        for y in 0..self.rules.height {
+            write!(f, "{:label$} ", y)?;
+            for x in 0..self.rules.width {
+                match self[(x, y)] {
+                    Some(color) => write!(f, "{} ", color)?,
+                    None => write!(f, "  ")?,
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        connect4,
+        game::{alpha_beta, MinimaxGame},
+        random::Rng,
+    };
+
+    use super::{Color, Game, Rules};
+
+    #[test]
+    pub fn parse_rules() {
+        assert_eq!("7,6,4".parse(), Ok(Rules::connect(7, 6, 4)));
+        assert_eq!("9, 9, 5, free".parse(), Ok(Rules::free(9, 9, 5)));
+
+        assert!("7,6".parse::<Rules>().is_err());
+        assert!("7,6,4,sideways".parse::<Rules>().is_err());
+        assert!("7,0,4".parse::<Rules>().is_err());
+        assert!("3,3,4".parse::<Rules>().is_err());
+    }
+
+    #[test]
+    pub fn matches_connect4() {
+        let mut rng = Rng::new(3);
+
+        for _ in 0..50 {
+            let mut game = Game::new(Rules::default());
+            let mut expected = connect4::Game::new();
+
+            while !expected.has_finished() {
+                assert!(!game.has_finished());
+
+                let moves: Vec<usize> = expected.legal_moves().collect();
+                assert_eq!(game.legal_moves().collect::<Vec<_>>(), moves);
+
+                let mv = moves[rng.below(moves.len())];
+                game = game.apply(mv);
+                expected = expected.apply(mv);
+
+                assert_eq!(format!("{}", game), format!("{}", expected));
+            }
+
+            assert!(game.has_finished());
+            assert_eq!(game.winner(), expected.winner());
+        }
+    }
+
+    #[test]
+    pub fn connect_five_on_a_free_board() {
+        let rules = Rules::free(9, 9, 5);
+        let mut game = Game::new(rules);
+
+        // White builds a diagonal while black plays along the bottom row
+        for i in 0..4 {
+            game = game.add_piece(i * 9 + i + 1).add_piece(8 * 9 + i);
+            assert!(!game.has_finished());
+        }
+
+        game = game.add_piece(4 * 9 + 5);
+        assert_eq!(game.winner(), Some(Color::White));
+        assert_eq!(game[(5, 4)], Some(Color::White));
+    }
+
+    #[test]
+    pub fn search_finds_winning_move() {
+        // Three in a row along the bottom of an 8x7 board, with both ends open
+        let game = Game::new(Rules::connect(8, 7, 4))
+            .add_piece(2)
+            .add_piece(2)
+            .add_piece(3)
+            .add_piece(3)
+            .add_piece(4)
+            .add_piece(4);
+
+        let best_move = alpha_beta(&game, 3).best_move;
+        assert!(best_move == 1 || best_move == 5);
+
+        let finished = game.add_piece(best_move);
+        assert_eq!(finished.winner(), Some(Color::White));
+    }
+
+    #[test]
+    pub fn tic_tac_toe_is_a_draw() {
+        let result = alpha_beta(&Game::new(Rules::free(3, 3, 3)), 9);
+
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    pub fn zobrist_key_is_order_independent() {
+        let rules = Rules::free(5, 5, 4);
+        let game = Game::new(rules).add_piece(0).add_piece(6).add_piece(12);
+        let transposed = Game::new(rules).add_piece(12).add_piece(6).add_piece(0);
+        let swapped = Game::new(rules).add_piece(6).add_piece(0).add_piece(12);
+
+        assert_eq!(game.zobrist_key(), transposed.zobrist_key());
+        assert_ne!(game.zobrist_key(), swapped.zobrist_key());
+    }
+
+    #[test]
+    pub fn display_labels_wide_boards() {
+        let game = Game::new(Rules::free(12, 11, 5)).add_piece(11);
+        let text = format!("{}", game);
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[0], "   0 1 2 3 4 5 6 7 8 9 0 1");
+        assert_eq!(lines[1].trim_end(), " 0                       x");
+        assert_eq!(lines.len(), 12);
+    }
+}
@@ -0,0 +1,398 @@
+//! Nim and Kayles, two impartial games: both players have the same moves, and whoever can't move
+//! loses. By the Sprague–Grundy theorem every position has a Grundy value, the player to move wins
+//! exactly when it isn't zero, and the value of several heaps or rows is the XOR of their values
+
+use std::fmt::Display;
+
+use crate::{
+    connect4::Color,
+    game::{MinimaxGame, WIN},
+    random,
+};
+
+const ZOBRIST_SEED: u64 = 0x4e1d_0000;
+
+/// Nim: players take turns to take any number of counters from one heap
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nim {
+    heaps: Vec<usize>,
+    current_player: Color,
+    hash: u64,
+}
+
+impl MinimaxGame for Nim {
+    /// The heap to take from and how many counters to take
+    type Move = (usize, usize);
+    type Moves = std::vec::IntoIter<(usize, usize)>;
+    type Player = Color;
+
+    fn to_move(&self) -> Color {
+        self.current_player
+    }
+
+    /// Only a finished game can be scored, as nothing else about the heaps says who is winning
+    /// without working out their Grundy value
+    fn evaluate(&self, depth: usize) -> i64 {
+        if self.has_finished() {
+            return -WIN - depth as i64; // Prefer earlier victories
+        }
+
+        0
+    }
+
+    fn has_finished(&self) -> bool {
+        self.heaps.iter().all(|heap| *heap == 0)
+    }
+
+    fn winner(&self) -> Option<Color> {
+        // The player who took the last counter wins
+        self.has_finished()
+            .then_some(self.current_player.opponent())
+    }
+
+    fn legal_moves(&self) -> Self::Moves {
+        let moves: Vec<(usize, usize)> = self
+            .heaps
+            .iter()
+            .enumerate()
+            .flat_map(|(heap, size)| (1..=*size).map(move |take| (heap, take)))
+            .collect();
+
+        moves.into_iter()
+    }
+
+    fn apply(&self, mv: (usize, usize)) -> Self {
+        let (heap, take) = mv;
+        let mut new_game = self.clone();
+
+        let size = self.heaps[heap];
+        new_game.heaps[heap] = size - take;
+        new_game.hash ^= heap_key(heap, size) ^ heap_key(heap, size - take);
+        new_game.current_player = self.current_player.opponent();
+
+        new_game
+    }
+
+    fn zobrist_key(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl Nim {
+    pub fn new(heaps: &[usize]) -> Self {
+        Nim {
+            heaps: heaps.to_vec(),
+            current_player: Color::White,
+            hash: heaps
+                .iter()
+                .enumerate()
+                .fold(0, |hash, (heap, size)| hash ^ heap_key(heap, *size)),
+        }
+    }
+
+    pub fn heaps(&self) -> &[usize] {
+        &self.heaps
+    }
+
+    /// The Grundy value of a heap is its size
+    pub fn grundy(&self) -> usize {
+        self.heaps.iter().fold(0, |value, heap| value ^ heap)
+    }
+}
+
+impl Display for Nim {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (heap, size) in self.heaps.iter().enumerate() {
+            writeln!(f, "{}: {}", heap, "|".repeat(*size))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Kayles: players take turns to knock down one pin, or two next to each other, from rows of
+/// pins. Knocking pins out of the middle of a row splits it in two
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Kayles {
+    /// The lengths of the rows, shortest first. The order of the rows doesn't matter, so keeping
+    /// them sorted means transposed positions are equal
+    rows: Vec<usize>,
+    current_player: Color,
+    hash: u64,
+}
+
+/// A move in Kayles: the row, the first pin knocked down, counting from 0, and the number of pins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KaylesMove {
+    pub row: usize,
+    pub start: usize,
+    pub pins: usize,
+}
+
+impl MinimaxGame for Kayles {
+    type Move = KaylesMove;
+    type Moves = std::vec::IntoIter<KaylesMove>;
+    type Player = Color;
+
+    fn to_move(&self) -> Color {
+        self.current_player
+    }
+
+    /// Only a finished game can be scored, as with Nim
+    fn evaluate(&self, depth: usize) -> i64 {
+        if self.has_finished() {
+            return -WIN - depth as i64; // Prefer earlier victories
+        }
+
+        0
+    }
+
+    fn has_finished(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    fn winner(&self) -> Option<Color> {
+        // The player who knocked down the last pin wins
+        self.has_finished()
+            .then_some(self.current_player.opponent())
+    }
+
+    /// Moves which give the same position are only listed once: rows of the same length are
+    /// interchangeable, and so are the two ends of a row
+    fn legal_moves(&self) -> Self::Moves {
+        let mut moves = vec![];
+
+        for (row, length) in self.rows.iter().enumerate() {
+            if row > 0 && self.rows[row - 1] == *length {
+                continue;
+            }
+
+            for pins in 1..=2.min(*length) {
+                let remaining = length - pins;
+                for start in 0..=remaining / 2 {
+                    moves.push(KaylesMove { row, start, pins });
+                }
+            }
+        }
+
+        moves.into_iter()
+    }
+
+    fn apply(&self, mv: KaylesMove) -> Self {
+        let mut new_game = self.clone();
+
+        let length = new_game.rows.remove(mv.row);
+        let left = mv.start;
+        let right = length - mv.start - mv.pins;
+
+        new_game.hash = new_game
+            .hash
+            .wrapping_sub(row_key(length))
+            .wrapping_add(row_key(left))
+            .wrapping_add(row_key(right));
+        new_game
+            .rows
+            .extend([left, right].into_iter().filter(|row| *row > 0));
+        new_game.rows.sort_unstable();
+        new_game.current_player = self.current_player.opponent();
+
+        new_game
+    }
+
+    fn zobrist_key(&self) -> u64 {
+        self.hash
+    }
+
+    /// Splitting a row into two equal halves is often strong, as the second player can then copy
+    /// every move in the other half
+    fn order_moves(&self, moves: &mut [KaylesMove]) {
+        moves.sort_by_key(|mv| {
+            let right = self.rows[mv.row] - mv.start - mv.pins;
+            (mv.start.abs_diff(right), mv.row, mv.start, mv.pins)
+        });
+    }
+}
+
+impl Kayles {
+    pub fn new(rows: &[usize]) -> Self {
+        let mut rows: Vec<usize> = rows.iter().copied().filter(|row| *row > 0).collect();
+        rows.sort_unstable();
+
+        Kayles {
+            hash: rows
+                .iter()
+                .fold(0, |hash: u64, row| hash.wrapping_add(row_key(*row))),
+            rows,
+            current_player: Color::White,
+        }
+    }
+
+    pub fn rows(&self) -> &[usize] {
+        &self.rows
+    }
+
+    pub fn grundy(&self) -> usize {
+        let longest = self.rows.last().copied().unwrap_or(0);
+        let values = kayles_values(longest + 1);
+
+        self.rows.iter().fold(0, |value, row| value ^ values[*row])
+    }
+}
+
+impl Display for Kayles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows: Vec<String> = self.rows.iter().map(|row| "i".repeat(*row)).collect();
+        writeln!(f, "{}", rows.join(" "))
+    }
+}
+
+/// The Grundy values of single rows of Kayles of every length below n. Each is the smallest value
+/// not reachable in one move, where the value of the two rows left behind is the XOR of theirs
+pub fn kayles_values(n: usize) -> Vec<usize> {
+    let mut values: Vec<usize> = Vec::with_capacity(n);
+
+    for length in 0..n {
+        let mut reachable = vec![false; length + 1];
+        for pins in 1..=2.min(length) {
+            for left in 0..=length - pins {
+                let right = length - pins - left;
+                let value = values[left] ^ values[right];
+                if value < reachable.len() {
+                    reachable[value] = true;
+                }
+            }
+        }
+
+        values.push(
+            reachable
+                .iter()
+                .position(|seen| !seen)
+                .unwrap_or(reachable.len()),
+        );
+    }
+
+    values
+}
+
+/// Heaps are told apart by their position, so a key depends on both
+fn heap_key(heap: usize, size: usize) -> u64 {
+    random::split_mix(ZOBRIST_SEED ^ ((heap as u64) << 32) ^ size as u64).1
+}
+
+/// Rows can be in any order, so their keys are added rather than XORed, which would cancel out
+/// two rows of the same length. An empty row has no key
+fn row_key(length: usize) -> u64 {
+    if length == 0 {
+        0
+    } else {
+        random::split_mix(ZOBRIST_SEED + length as u64).1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        connect4::Color,
+        game::{alpha_beta, alpha_beta_tt, transposition::TranspositionTable, MinimaxGame},
+    };
+
+    use super::{kayles_values, Kayles, KaylesMove, Nim};
+
+    #[test]
+    pub fn kayles_grundy_values() {
+        assert_eq!(kayles_values(12), vec![0, 1, 2, 3, 1, 4, 3, 2, 1, 4, 2, 6]);
+
+        assert_eq!(Kayles::new(&[]).grundy(), 0);
+        assert_eq!(Kayles::new(&[4, 5]).grundy(), 1 ^ 4);
+        assert_eq!(Kayles::new(&[3, 3]).grundy(), 0);
+    }
+
+    #[test]
+    pub fn nim_search_matches_grundy() {
+        for a in 0..=3 {
+            for b in 0..=3 {
+                for c in 0..=3 {
+                    let game = Nim::new(&[a, b, c]);
+                    if game.has_finished() {
+                        continue;
+                    }
+
+                    let result = alpha_beta(&game, a + b + c);
+                    assert_eq!(result.score > 0, game.grundy() != 0, "{:?}", game.heaps());
+
+                    // A winning move leaves a position worth zero
+                    if game.grundy() != 0 {
+                        assert_eq!(game.apply(result.best_move).grundy(), 0);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn kayles_search_matches_grundy() {
+        let mut table = TranspositionTable::new(1 << 12);
+
+        for first in 1..=8 {
+            for second in 0..=3 {
+                let game = Kayles::new(&[first, second]);
+                let best = alpha_beta_tt(&game, first + second, &mut table);
+                let score = alpha_beta(&game, first + second).score;
+
+                assert_eq!(score > 0, game.grundy() != 0, "{:?}", game.rows());
+                if game.grundy() != 0 {
+                    assert_eq!(game.apply(best).grundy(), 0, "{:?}", game.rows());
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn kayles_moves() {
+        let game = Kayles::new(&[5, 2, 2]);
+        assert_eq!(game.rows(), &[2, 2, 5]);
+
+        // Only one of the rows of two is moved in, and moves mirroring another from the other end
+        // of a row are left out
+        let moves: Vec<KaylesMove> = game.legal_moves().collect();
+        assert_eq!(moves.len(), 2 + 3 + 2);
+
+        // Knocking down the middle pin splits the row
+        let split = game.apply(KaylesMove {
+            row: 2,
+            start: 2,
+            pins: 1,
+        });
+        assert_eq!(split.rows(), &[2, 2, 2, 2]);
+        assert_eq!(
+            split.zobrist_key(),
+            Kayles::new(&[2, 2, 2, 2]).zobrist_key()
+        );
+        assert_ne!(split.zobrist_key(), Kayles::new(&[2, 2]).zobrist_key());
+
+        let finished = Kayles::new(&[2]).apply(KaylesMove {
+            row: 0,
+            start: 0,
+            pins: 2,
+        });
+        assert!(finished.has_finished());
+        assert_eq!(finished.winner(), Some(Color::White));
+    }
+
+    #[test]
+    pub fn nim_moves() {
+        let game = Nim::new(&[1, 2]);
+        assert_eq!(
+            game.legal_moves().collect::<Vec<_>>(),
+            vec![(0, 1), (1, 1), (1, 2)]
+        );
+
+        let game = game.apply((1, 1)).apply((0, 1));
+        assert_eq!(game.heaps(), &[0, 1]);
+        assert_eq!(game.zobrist_key(), Nim::new(&[0, 1]).zobrist_key());
+        assert_eq!(format!("{}", game), "0: \n1: |\n");
+
+        let game = game.apply((1, 1));
+        assert_eq!(game.winner(), Some(Color::White));
+    }
+}
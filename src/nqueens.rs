@@ -1,4 +1,7 @@
-use std::fmt::Display;
+use std::{fmt::Display, thread};
+
+/// The largest board the bitmask solvers support
+pub const MAX_BITBOARD_SIZE: usize = u64::BITS as usize;
 
 pub fn solve<const N: usize>() -> Vec<Queens<N>> {
     let queen = Queens::<N>::new();
@@ -19,7 +22,109 @@ fn solve_internal<const N: usize>(row: usize, root: Queens<N>) -> Vec<Queens<N>>
     solutions
 }
 
-#[derive(Clone)]
+/// Counts the solutions for an n x n board using bitmask backtracking
+pub fn count_solutions(n: usize) -> u64 {
+    first_row_placements(n)
+        .map(|(column, weight)| weight * count_from_first_row(n, column))
+        .sum()
+}
+
+/// Counts the solutions for an n x n board, searching each first row placement on its own thread
+pub fn count_solutions_parallel(n: usize) -> u64 {
+    thread::scope(|scope| {
+        let handles: Vec<_> = first_row_placements(n)
+            .map(|(column, weight)| scope.spawn(move || weight * count_from_first_row(n, column)))
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("counting thread panicked"))
+            .sum()
+    })
+}
+
+/// Returns the first row columns worth searching along with how many solutions each one stands for.
+/// Solutions with the first queen in the right half are mirrors of those in the left half, so only
+/// the left half (and the middle column on odd boards) needs to be searched
+fn first_row_placements(n: usize) -> impl Iterator<Item = (usize, u64)> {
+    check_size(n);
+
+    let middle = (n % 2 == 1).then_some((n / 2, 1));
+    (0..n / 2).map(|column| (column, 2)).chain(middle)
+}
+
+fn count_from_first_row(n: usize, column: usize) -> u64 {
+    let all = full_mask(n);
+    let queen = 1 << column;
+
+    count_bitboard(all, queen, (queen << 1) & all, queen >> 1)
+}
+
+/// Panics if an n x n board has too many columns for a bitboard
+fn check_size(n: usize) {
+    assert!(
+        n <= MAX_BITBOARD_SIZE,
+        "board size {} is larger than {}",
+        n,
+        MAX_BITBOARD_SIZE
+    );
+}
+
+/// Returns a mask with one bit set for each column of an n x n board
+fn full_mask(n: usize) -> u64 {
+    check_size(n);
+
+    if n == 0 {
+        0
+    } else {
+        u64::MAX >> (MAX_BITBOARD_SIZE - n)
+    }
+}
+
+/// Counts the ways to fill the remaining rows, given the columns and diagonals attacked on the next row
+fn count_bitboard(all: u64, columns: u64, left: u64, right: u64) -> u64 {
+    if columns == all {
+        return 1;
+    }
+
+    let mut count = 0;
+    let mut free = all & !(columns | left | right);
+
+    while free != 0 {
+        // Take the lowest free square
+        let queen = free & free.wrapping_neg();
+        free ^= queen;
+
+        count += count_bitboard(
+            all,
+            columns | queen,
+            ((left | queen) << 1) & all,
+            (right | queen) >> 1,
+        );
+    }
+
+    count
+}
+
+/// Returns only the solutions which are not rotations or reflections of another returned solution
+pub fn solve_fundamental<const N: usize>() -> Vec<FundamentalSolution<N>> {
+    solve::<N>()
+        .into_iter()
+        .filter(|solution| *solution == solution.canonical())
+        .map(|solution| FundamentalSolution {
+            orbit_size: solution.orbit_size(),
+            solution,
+        })
+        .collect()
+}
+
+/// A canonical solution, along with the number of distinct solutions its symmetries produce
+pub struct FundamentalSolution<const N: usize> {
+    pub solution: Queens<N>,
+    pub orbit_size: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Queens<const N: usize> {
     board: [[bool; N]; N],
 }
@@ -75,6 +180,63 @@ impl<const N: usize> Queens<N> {
         true
     }
 
+    /// Returns the board rotated a quarter turn clockwise
+    pub fn rotate(&self) -> Queens<N> {
+        let mut rotated = Queens::new();
+        for (y, row) in self.board.iter().enumerate() {
+            for (x, queen) in row.iter().enumerate() {
+                rotated.board[x][N - 1 - y] = *queen;
+            }
+        }
+
+        rotated
+    }
+
+    /// Returns the board mirrored left to right
+    pub fn reflect(&self) -> Queens<N> {
+        let mut reflected = self.clone();
+        for row in reflected.board.iter_mut() {
+            row.reverse();
+        }
+
+        reflected
+    }
+
+    /// Returns the 8 images of the board under the symmetries of the square, starting with the identity
+    pub fn symmetries(&self) -> [Queens<N>; 8] {
+        let r1 = self.rotate();
+        let r2 = r1.rotate();
+        let r3 = r2.rotate();
+
+        [
+            self.clone(),
+            r1.clone(),
+            r2.clone(),
+            r3.clone(),
+            self.reflect(),
+            r1.reflect(),
+            r2.reflect(),
+            r3.reflect(),
+        ]
+    }
+
+    /// Returns the smallest of the board's symmetric images, which is the same for every board in the orbit
+    pub fn canonical(&self) -> Queens<N> {
+        self.symmetries()
+            .into_iter()
+            .min()
+            .expect("there are always 8 symmetries")
+    }
+
+    /// Returns the number of distinct boards that the symmetries of the square map this board to
+    pub fn orbit_size(&self) -> usize {
+        let mut images = self.symmetries().to_vec();
+        images.sort();
+        images.dedup();
+
+        images.len()
+    }
+
     pub fn place_queen(&self, x: usize, y: usize) -> Result<Queens<N>, String> {
         if x >= N || y >= N {
             return Err("Coordinates out of range".to_string());
@@ -110,9 +272,191 @@ impl<const N: usize> Display for Queens<N> {
     }
 }
 
+/// Returns an iterator which lazily finds every solution for an n x n board chosen at runtime
+pub fn solutions(n: usize) -> Solutions {
+    let all = full_mask(n);
+    let stack = if n == 0 {
+        vec![]
+    } else {
+        vec![Frame {
+            free: all,
+            columns: 0,
+            left: 0,
+            right: 0,
+        }]
+    };
+
+    Solutions {
+        all,
+        stack,
+        placement: Vec::with_capacity(n),
+    }
+}
+
+/// Builds a single solution for an n x n board directly, without any search. Solutions exist for
+/// every n except 2 and 3, for which None is returned
+pub fn construct(n: usize) -> Option<Board> {
+    if n == 2 || n == 3 {
+        return None;
+    }
+
+    // Queens go on the even columns then the odd columns (counting from 1), which only clashes
+    // when n mod 6 is 2 or 3. Those cases are fixed by moving a few queens to the end of each list
+    let mut evens: Vec<usize> = (2..=n).step_by(2).collect();
+    let mut odds: Vec<usize> = (1..=n).step_by(2).collect();
+
+    match n % 6 {
+        2 => {
+            odds.swap(0, 1);
+            let five = odds.remove(2);
+            odds.push(five);
+        }
+        3 => {
+            let two = evens.remove(0);
+            evens.push(two);
+            odds.rotate_left(2);
+        }
+        _ => {}
+    }
+
+    Some(Board::new(
+        evens
+            .into_iter()
+            .chain(odds)
+            .map(|column| column - 1)
+            .collect(),
+    ))
+}
+
+/// A board whose size is chosen at runtime, stored as the column of the queen on each row
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Board {
+    columns: Vec<usize>,
+}
+
+impl Board {
+    pub fn new(columns: Vec<usize>) -> Self {
+        Board { columns }
+    }
+
+    /// The number of rows and columns on the board
+    pub fn size(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// The column of the queen on each row
+    pub fn columns(&self) -> &[usize] {
+        &self.columns
+    }
+
+    /// Returns true if no two queens attack each other, in O(n) time
+    pub fn is_valid(&self) -> bool {
+        let n = self.size();
+        let mut columns = vec![false; n];
+        let mut diagonals = vec![false; 2 * n];
+        let mut anti_diagonals = vec![false; 2 * n];
+
+        for (y, x) in self.columns.iter().enumerate() {
+            if *x >= n {
+                return false;
+            }
+
+            let diagonal = x + n - y;
+            let anti_diagonal = x + y;
+            if columns[*x] || diagonals[diagonal] || anti_diagonals[anti_diagonal] {
+                return false;
+            }
+
+            columns[*x] = true;
+            diagonals[diagonal] = true;
+            anti_diagonals[anti_diagonal] = true;
+        }
+
+        true
+    }
+}
+
+impl Display for Board {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for column in self.columns.iter() {
+            for x in 0..self.size() {
+                if x == *column {
+                    write!(f, "Q ")?;
+                } else {
+                    write!(f, ". ")?;
+                }
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Streams solutions one at a time, only holding the current partial placement in memory
+pub struct Solutions {
+    all: u64,
+    stack: Vec<Frame>,
+    placement: Vec<usize>,
+}
+
+/// The backtracking state for a single row
+struct Frame {
+    free: u64,
+    columns: u64,
+    left: u64,
+    right: u64,
+}
+
+impl Iterator for Solutions {
+    type Item = Board;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(frame) = self.stack.last_mut() {
+            if frame.free == 0 {
+                // Every square on this row has been tried, so backtrack
+                self.stack.pop();
+                self.placement.pop();
+                continue;
+            }
+
+            let queen = frame.free & frame.free.wrapping_neg();
+            frame.free ^= queen;
+
+            let columns = frame.columns | queen;
+            let left = ((frame.left | queen) << 1) & self.all;
+            let right = (frame.right | queen) >> 1;
+
+            let row = self.stack.len() - 1;
+            self.placement.truncate(row);
+            self.placement.push(queen.trailing_zeros() as usize);
+
+            if columns == self.all {
+                return Some(Board::new(self.placement.clone()));
+            }
+
+            self.stack.push(Frame {
+                free: self.all & !(columns | left | right),
+                columns,
+                left,
+                right,
+            });
+        }
+
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Queens;
+    use super::{
+        construct, count_solutions, count_solutions_parallel, solutions, solve, solve_fundamental,
+        Board, Queens,
+    };
+
+    // Known solution counts for boards of size 1 to 12
+    const SOLUTION_COUNTS: [u64; 12] = [1, 0, 0, 2, 10, 4, 40, 92, 352, 724, 2680, 14200];
 
     #[test]
     pub fn safe_place() {
@@ -148,4 +492,145 @@ mod tests {
         assert_eq!(moves.len(), 1);
         assert!(moves[0].board[3][2]);
     }
+
+    #[test]
+    pub fn count_solutions_matches_known_counts() {
+        for (n, expected) in (1..).zip(SOLUTION_COUNTS) {
+            assert_eq!(count_solutions(n), expected, "n = {}", n);
+        }
+    }
+
+    #[test]
+    pub fn count_solutions_parallel_matches_known_counts() {
+        for (n, expected) in (1..).zip(SOLUTION_COUNTS) {
+            assert_eq!(count_solutions_parallel(n), expected, "n = {}", n);
+        }
+    }
+
+    #[test]
+    #[ignore = "takes minutes, so run it with --release"]
+    pub fn count_solutions_large_boards() {
+        // Known solution counts for boards of size 13 to 18
+        let counts: [u64; 6] = [
+            73_712,
+            365_596,
+            2_279_184,
+            14_772_512,
+            95_815_104,
+            666_090_624,
+        ];
+
+        for (n, expected) in (13..).zip(counts) {
+            assert_eq!(count_solutions_parallel(n), expected, "n = {}", n);
+        }
+    }
+
+    #[test]
+    pub fn count_solutions_matches_solve() {
+        assert_eq!(solve::<6>().len() as u64, count_solutions(6));
+    }
+
+    #[test]
+    pub fn symmetries() {
+        let queens = Queens::<4>::new().place_queen(1, 0).unwrap();
+        let symmetries = queens.symmetries();
+
+        assert!(symmetries[0] == queens);
+        assert!(symmetries[1].board[1][3]);
+        assert!(symmetries[2].board[3][2]);
+        assert!(symmetries[3].board[2][0]);
+        assert!(symmetries[4].board[0][2]);
+        assert!(symmetries.iter().all(|image| image
+            .board
+            .iter()
+            .flatten()
+            .filter(|q| **q)
+            .count()
+            == 1));
+    }
+
+    #[test]
+    pub fn solve_fundamental_matches_known_counts() {
+        assert_eq!(solve_fundamental::<4>().len(), 1);
+        assert_eq!(solve_fundamental::<5>().len(), 2);
+        assert_eq!(solve_fundamental::<6>().len(), 1);
+        assert_eq!(solve_fundamental::<7>().len(), 6);
+        assert_eq!(solve_fundamental::<8>().len(), 12);
+    }
+
+    #[test]
+    pub fn orbit_sizes_cover_all_solutions() {
+        let fundamental = solve_fundamental::<8>();
+
+        assert_eq!(
+            fundamental.iter().map(|f| f.orbit_size).sum::<usize>(),
+            solve::<8>().len()
+        );
+
+        // Exactly one of the 8x8 fundamental solutions is symmetric under a half turn
+        assert_eq!(fundamental.iter().filter(|f| f.orbit_size == 4).count(), 1);
+    }
+
+    #[test]
+    pub fn solutions_matches_known_counts() {
+        for (n, expected) in (1..).zip(SOLUTION_COUNTS) {
+            assert_eq!(solutions(n).count() as u64, expected, "n = {}", n);
+        }
+    }
+
+    #[test]
+    pub fn solutions_are_valid() {
+        for board in solutions(8) {
+            for (y1, x1) in board.columns().iter().enumerate() {
+                for (y2, x2) in board.columns().iter().enumerate().skip(y1 + 1) {
+                    assert_ne!(x1, x2);
+                    assert_ne!(x1.abs_diff(*x2), y2 - y1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn is_valid_accepts_solutions() {
+        for board in solutions(8) {
+            assert!(board.is_valid());
+        }
+    }
+
+    #[test]
+    pub fn solutions_are_lazy() {
+        let mut solutions = solutions(6);
+
+        assert_eq!(solutions.next(), Some(Board::new(vec![1, 3, 5, 0, 2, 4])));
+        assert_eq!(solutions.next(), Some(Board::new(vec![2, 5, 1, 4, 0, 3])));
+    }
+
+    #[test]
+    pub fn is_valid() {
+        assert!(Board::new(vec![1, 3, 0, 2]).is_valid());
+        assert!(!Board::new(vec![1, 3, 0, 1]).is_valid());
+        assert!(!Board::new(vec![0, 2, 4, 1, 3, 5]).is_valid());
+        assert!(!Board::new(vec![0, 4]).is_valid());
+    }
+
+    #[test]
+    pub fn construct_small_boards() {
+        assert_eq!(construct(1), Some(Board::new(vec![0])));
+        assert_eq!(construct(2), None);
+        assert_eq!(construct(3), None);
+
+        // Cover every residue mod 6
+        for n in 4..=200 {
+            let board = construct(n).unwrap();
+            assert_eq!(board.size(), n);
+            assert!(board.is_valid(), "n = {}", n);
+        }
+    }
+
+    #[test]
+    pub fn construct_large_board() {
+        for n in [999_998, 999_999, 1_000_000] {
+            assert!(construct(n).unwrap().is_valid(), "n = {}", n);
+        }
+    }
 }
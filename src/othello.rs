@@ -0,0 +1,444 @@
+//! Othello, also called Reversi. A player who has no move which flips a disc has to pass, and the
+//! game ends when neither player can move
+
+use std::{fmt::Display, ops::Index};
+
+use crate::{
+    connect4::Color,
+    game::{MinimaxGame, WIN},
+    random,
+};
+
+/// One Zobrist key for each color in each square, and one for the side to move, as a pass changes
+/// it without changing the board
+const ZOBRIST_KEYS: [u64; 129] = random::zobrist_keys(0x07e1_1000);
+
+/// Every square but those in the left or right hand column, which a shift across would wrap out of
+const NOT_A_FILE: u64 = 0xfefe_fefe_fefe_fefe;
+const NOT_H_FILE: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+
+const CORNERS: u64 = 0x8100_0000_0000_0081;
+
+/// The eight directions as a shift, positive for towards higher squares, and a mask of the squares
+/// a disc can move to without wrapping around the board
+const DIRECTIONS: [(i32, u64); 8] = [
+    (1, NOT_A_FILE),
+    (-1, NOT_H_FILE),
+    (8, u64::MAX),
+    (-8, u64::MAX),
+    (9, NOT_A_FILE),
+    (7, NOT_H_FILE),
+    (-7, NOT_A_FILE),
+    (-9, NOT_H_FILE),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Move {
+    /// Places a disc on a square, counting along each row from the top left
+    Place(usize),
+    /// Only allowed when no disc can be placed
+    Pass,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    /// A bitboard of each color's discs, where bit y * 8 + x is column x of row y from the top
+    discs: [u64; 2],
+    /// Black moves first
+    current_player: Color,
+    hash: u64,
+}
+
+impl Index<(usize, usize)> for Game {
+    type Output = Option<Color>;
+
+    /// Returns the disc in column x, row y, where row 0 is the top of the board
+    fn index(&self, index: (usize, usize)) -> &Self::Output {
+        let (x, y) = index;
+        if x >= 8 || y >= 8 {
+            return &None;
+        }
+
+        let bit = 1 << (y * 8 + x);
+        if self.discs[Color::White.index()] & bit != 0 {
+            &Some(Color::White)
+        } else if self.discs[Color::Black.index()] & bit != 0 {
+            &Some(Color::Black)
+        } else {
+            &None
+        }
+    }
+}
+
+impl MinimaxGame for Game {
+    type Move = Move;
+    type Moves = std::vec::IntoIter<Move>;
+    type Player = Color;
+
+    fn to_move(&self) -> Color {
+        self.current_player
+    }
+
+    /// Finished games are scored by who has more discs. Otherwise having more moves and holding
+    /// corners, which can never be flipped, count for more than the number of discs
+    fn evaluate(&self, depth: usize) -> i64 {
+        let (ours, theirs) = self.sides();
+        let discs = ours.count_ones() as i64 - theirs.count_ones() as i64;
+
+        if self.has_finished() {
+            return match discs {
+                0 => 0,
+                discs if discs > 0 => WIN + depth as i64 + discs, // Prefer earlier victories
+                discs => -WIN - depth as i64 + discs,
+            };
+        }
+
+        let mobility = placements(ours, theirs).count_ones() as i64
+            - placements(theirs, ours).count_ones() as i64;
+        let corners = (ours & CORNERS).count_ones() as i64 - (theirs & CORNERS).count_ones() as i64;
+
+        discs + 10 * mobility + 50 * corners
+    }
+
+    fn has_finished(&self) -> bool {
+        let (ours, theirs) = self.sides();
+        placements(ours, theirs) == 0 && placements(theirs, ours) == 0
+    }
+
+    /// The player with more discs at the end wins
+    fn winner(&self) -> Option<Color> {
+        if !self.has_finished() {
+            return None;
+        }
+
+        let white = self.count(Color::White);
+        let black = self.count(Color::Black);
+        if white > black {
+            Some(Color::White)
+        } else if black > white {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    }
+
+    fn legal_moves(&self) -> Self::Moves {
+        let (ours, theirs) = self.sides();
+        let mut placements = placements(ours, theirs);
+
+        let mut moves = vec![];
+        while placements != 0 {
+            moves.push(Move::Place(placements.trailing_zeros() as usize));
+            placements &= placements - 1;
+        }
+
+        if moves.is_empty() && !self.has_finished() {
+            moves.push(Move::Pass);
+        }
+
+        moves.into_iter()
+    }
+
+    fn apply(&self, mv: Move) -> Self {
+        self.play(mv)
+    }
+
+    fn zobrist_key(&self) -> u64 {
+        self.hash
+    }
+
+    /// Corners can't be flipped back, so try them first, and the squares next to them last
+    fn order_moves(&self, moves: &mut [Move]) {
+        moves.sort_by_key(|mv| match mv {
+            Move::Place(square) if CORNERS & (1 << square) != 0 => (0, *square),
+            Move::Place(square) if next_to_corner(*square) => (2, *square),
+            Move::Place(square) => (1, *square),
+            Move::Pass => (0, 0),
+        });
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    /// The usual start, with two discs of each color crossed in the centre
+    pub fn new() -> Self {
+        let mut game = Game {
+            discs: [0, 0],
+            current_player: Color::Black,
+            hash: 0,
+        };
+
+        for (square, color) in [
+            (27, Color::White),
+            (28, Color::Black),
+            (35, Color::Black),
+            (36, Color::White),
+        ] {
+            game.discs[color.index()] |= 1 << square;
+            game.hash ^= zobrist_key(color, square);
+        }
+
+        game
+    }
+
+    /// Plays a legal move for the player to move, flipping every line of the opponent's discs it
+    /// closes off
+    pub fn play(&self, mv: Move) -> Self {
+        let mut new_board = self.clone();
+        new_board.current_player = self.current_player.opponent();
+        new_board.hash ^= ZOBRIST_KEYS[128];
+
+        let Move::Place(square) = mv else {
+            return new_board;
+        };
+
+        let (ours, theirs) = self.sides();
+        let placed = 1 << square;
+        let flipped = flips(placed, ours, theirs);
+
+        let us = self.current_player.index();
+        new_board.discs[us] |= placed | flipped;
+        new_board.discs[1 - us] &= !flipped;
+
+        new_board.hash ^= zobrist_key(self.current_player, square);
+        let mut remaining = flipped;
+        while remaining != 0 {
+            let square = remaining.trailing_zeros() as usize;
+            new_board.hash ^= zobrist_key(self.current_player, square)
+                ^ zobrist_key(self.current_player.opponent(), square);
+            remaining &= remaining - 1;
+        }
+
+        new_board
+    }
+
+    /// The number of discs of a color on the board
+    pub fn count(&self, color: Color) -> u32 {
+        self.discs[color.index()].count_ones()
+    }
+
+    /// The discs of the player to move and of their opponent
+    fn sides(&self) -> (u64, u64) {
+        let us = self.current_player.index();
+        (self.discs[us], self.discs[1 - us])
+    }
+}
+
+/// Moves every disc one step in a direction, dropping any which would wrap around the board
+fn shift(discs: u64, direction: (i32, u64)) -> u64 {
+    let (amount, mask) = direction;
+    let moved = if amount > 0 {
+        discs << amount
+    } else {
+        discs >> -amount
+    };
+
+    moved & mask
+}
+
+/// The empty squares where placing a disc would flip at least one of the opponent's
+fn placements(ours: u64, theirs: u64) -> u64 {
+    let empty = !(ours | theirs);
+    let mut moves = 0;
+
+    for direction in DIRECTIONS {
+        // Follow runs of the opponent's discs out from ours. A line can hold at most six
+        let mut run = shift(ours, direction) & theirs;
+        for _ in 0..5 {
+            run |= shift(run, direction) & theirs;
+        }
+        moves |= shift(run, direction) & empty;
+    }
+
+    moves
+}
+
+/// The opponent's discs flipped by placing a disc
+fn flips(placed: u64, ours: u64, theirs: u64) -> u64 {
+    let mut flipped = 0;
+
+    for direction in DIRECTIONS {
+        let mut run = 0;
+        let mut next = shift(placed, direction);
+        while next & theirs != 0 {
+            run |= next;
+            next = shift(next, direction);
+        }
+
+        // Only a line ending in one of our discs is flipped
+        if next & ours != 0 {
+            flipped |= run;
+        }
+    }
+
+    flipped
+}
+
+fn next_to_corner(square: usize) -> bool {
+    let (x, y) = (square % 8, square / 8);
+    (x <= 1 || x >= 6) && (y <= 1 || y >= 6)
+}
+
+fn zobrist_key(color: Color, square: usize) -> u64 {
+    ZOBRIST_KEYS[color.index() * 64 + square]
+}
+
+impl Display for Game {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  a b c d e f g h")?;
+
+        for y in 0..8 {
+            write!(f, "{} ", y + 1)?;
+            for x in 0..8 {
+                match self[(x, y)] {
+                    Some(Color::White) => write!(f, "W ")?,
+                    Some(Color::Black) => write!(f, "B ")?,
+                    None => write!(f, ". ")?,
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        connect4::Color,
+        game::{alpha_beta, MinimaxGame},
+        random::Rng,
+    };
+
+    use super::{zobrist_key, Game, Move, ZOBRIST_KEYS};
+
+    /// Counts the move sequences of a given length
+    fn perft(game: &Game, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        game.legal_moves()
+            .map(|mv| perft(&game.apply(mv), depth - 1))
+            .sum()
+    }
+
+    /// Builds a position from rows of B, W and . from the top
+    fn position(rows: &[&str], to_move: Color) -> Game {
+        let mut game = Game {
+            discs: [0, 0],
+            current_player: to_move,
+            hash: 0,
+        };
+
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let color = match c {
+                    'W' => Color::White,
+                    'B' => Color::Black,
+                    _ => continue,
+                };
+                game.discs[color.index()] |= 1 << (y * 8 + x);
+                game.hash ^= zobrist_key(color, y * 8 + x);
+            }
+        }
+
+        if to_move == Color::White {
+            game.hash ^= ZOBRIST_KEYS[128];
+        }
+
+        game
+    }
+
+    #[test]
+    pub fn perft_from_the_start() {
+        let game = Game::new();
+
+        for (depth, count) in [(1, 4), (2, 12), (3, 56), (4, 244), (5, 1396)] {
+            assert_eq!(perft(&game, depth), count, "depth {}", depth);
+        }
+    }
+
+    #[test]
+    pub fn flips_every_closed_line() {
+        // Black plays d3, flipping d4
+        let game = Game::new().apply(Move::Place(19));
+
+        assert_eq!(game.count(Color::Black), 4);
+        assert_eq!(game.count(Color::White), 1);
+        assert_eq!(game[(3, 3)], Some(Color::Black));
+        assert_eq!(game.to_move(), Color::White);
+
+        // A disc closing lines in two directions flips both
+        let game = position(&["B.B.....", ".WW....."], Color::Black);
+        let game = game.apply(Move::Place(18));
+        assert_eq!(game.count(Color::White), 0);
+        assert_eq!(game.count(Color::Black), 5);
+    }
+
+    #[test]
+    pub fn passes() {
+        // White has no disc to flip, but black can still take white's disc
+        let game = position(&["BW......"], Color::White);
+
+        assert!(!game.has_finished());
+        assert_eq!(game.legal_moves().collect::<Vec<_>>(), vec![Move::Pass]);
+
+        let game = game.apply(Move::Pass);
+        assert_eq!(game.to_move(), Color::Black);
+        assert_ne!(
+            game.zobrist_key(),
+            position(&["BW......"], Color::White).zobrist_key()
+        );
+        assert_eq!(game.legal_moves().collect::<Vec<_>>(), vec![Move::Place(2)]);
+
+        // Then nobody can move and black has every disc
+        let game = game.apply(Move::Place(2));
+        assert!(game.has_finished());
+        assert_eq!(game.legal_moves().count(), 0);
+        assert_eq!(game.winner(), Some(Color::Black));
+    }
+
+    #[test]
+    pub fn hash_is_updated_incrementally() {
+        let mut rng = Rng::new(49);
+
+        for _ in 0..20 {
+            let mut game = Game::new();
+
+            while !game.has_finished() {
+                let moves: Vec<Move> = game.legal_moves().collect();
+                game = game.apply(moves[rng.below(moves.len())]);
+
+                let mut rows = vec![String::new(); 8];
+                for (y, row) in rows.iter_mut().enumerate() {
+                    for x in 0..8 {
+                        row.push(match game[(x, y)] {
+                            Some(Color::White) => 'W',
+                            Some(Color::Black) => 'B',
+                            None => '.',
+                        });
+                    }
+                }
+                let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+                assert_eq!(position(&rows, game.to_move()), game);
+            }
+        }
+    }
+
+    #[test]
+    pub fn search_takes_the_last_disc() {
+        // Black can flip white's only disc and win at once
+        let game = position(&["BW......", "........", "......BB"], Color::Black);
+        let result = alpha_beta(&game, 3);
+
+        assert_eq!(result.best_move, Move::Place(2));
+        assert!(result.score > 0);
+    }
+}
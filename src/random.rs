@@ -0,0 +1,109 @@
+//! A small, dependency free pseudo random number generator based on SplitMix64
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Advances a SplitMix64 state, returning the new state and the next output.
+/// This is a const fn so it can be used to build tables of random keys at compile time
+pub const fn split_mix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(GOLDEN_GAMMA);
+
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+    (state, z ^ (z >> 31))
+}
+
+/// Builds a table of random keys at compile time, for Zobrist hashing
+pub const fn zobrist_keys<const N: usize>(seed: u64) -> [u64; N] {
+    let mut keys = [0; N];
+    let mut state = seed;
+
+    let mut i = 0;
+    while i < N {
+        let (next, key) = split_mix(state);
+        state = next;
+        keys[i] = key;
+        i += 1;
+    }
+
+    keys
+}
+
+/// A seedable SplitMix64 generator. It is fast and good enough for playouts and test data, but not
+/// for anything that needs to be unpredictable
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let (state, value) = split_mix(self.state);
+        self.state = state;
+
+        value
+    }
+
+    /// Returns a value in 0..bound
+    pub fn below(&mut self, bound: usize) -> usize {
+        assert!(bound > 0, "bound must be positive");
+
+        // Multiplying maps the 64 bit output onto the range with negligible bias
+        ((self.next_u64() as u128 * bound as u128) >> 64) as usize
+    }
+
+    /// Returns a value in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{zobrist_keys, Rng};
+
+    #[test]
+    pub fn zobrist_keys_are_distinct() {
+        let mut keys = zobrist_keys::<64>(1).to_vec();
+        keys.sort();
+        keys.dedup();
+
+        assert_eq!(keys.len(), 64);
+    }
+
+    #[test]
+    pub fn rng_is_deterministic() {
+        let mut a = Rng::new(7);
+        let mut b = Rng::new(7);
+
+        for _ in 0..10 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    pub fn below() {
+        let mut rng = Rng::new(3);
+        let mut counts = [0; 4];
+
+        for _ in 0..4000 {
+            counts[rng.below(4)] += 1;
+        }
+
+        assert!(counts.iter().all(|count| (800..1200).contains(count)));
+    }
+
+    #[test]
+    pub fn next_f64() {
+        let mut rng = Rng::new(5);
+
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&rng.next_f64()));
+        }
+    }
+}
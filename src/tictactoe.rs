@@ -0,0 +1,204 @@
+//! Tic-tac-toe. Small enough to search completely, and a draw with perfect play, so it checks
+//! search code against a value that is known.
+//!
+//! It is the 3,3,3 game without gravity, so the rules come from `mnk`. On top of them it orders
+//! moves for the board, stops listing moves once the game is won, and prints the board compactly
+
+use std::{fmt::Display, ops::Index};
+
+use crate::{
+    connect4::Color,
+    game::MinimaxGame,
+    mnk::{self, Rules},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game(mnk::Game);
+
+impl Index<(usize, usize)> for Game {
+    type Output = Option<Color>;
+
+    /// Returns the piece in column x, row y, where row 0 is the top of the board
+    fn index(&self, index: (usize, usize)) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl MinimaxGame for Game {
+    /// The index of a space, counting along each row from the top
+    type Move = usize;
+    type Moves = std::vec::IntoIter<usize>;
+    type Player = Color;
+
+    fn to_move(&self) -> Color {
+        self.0.to_move()
+    }
+
+    fn evaluate(&self, depth: usize) -> i64 {
+        self.0.evaluate(depth)
+    }
+
+    fn has_finished(&self) -> bool {
+        self.0.has_finished()
+    }
+
+    fn winner(&self) -> Option<Color> {
+        self.0.winner()
+    }
+
+    /// An m,n,k game lists the empty spaces even after a win, but here a finished game has no moves
+    fn legal_moves(&self) -> Self::Moves {
+        if self.has_finished() {
+            vec![].into_iter()
+        } else {
+            self.0.legal_moves()
+        }
+    }
+
+    fn apply(&self, mv: usize) -> Self {
+        self.add_piece(mv)
+    }
+
+    fn zobrist_key(&self) -> u64 {
+        self.0.zobrist_key()
+    }
+
+    /// The centre is in four lines and the corners in three, so try them before the edges
+    fn order_moves(&self, moves: &mut [usize]) {
+        moves.sort_by_key(|space| match space {
+            4 => (0, *space),
+            0 | 2 | 6 | 8 => (1, *space),
+            _ => (2, *space),
+        });
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Game(mnk::Game::new(Rules::free(3, 3, 3)))
+    }
+
+    /// Places a piece for the player to move in an empty space
+    pub fn add_piece(&self, space: usize) -> Self {
+        Game(self.0.add_piece(space))
+    }
+
+    /// Returns true if every space is full
+    pub fn has_tied(&self) -> bool {
+        self.0.has_tied()
+    }
+
+    /// Returns true if the last piece played completed a line
+    pub fn has_won(&self) -> bool {
+        self.0.has_won()
+    }
+}
+
+impl Display for Game {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for y in 0..3 {
+            for x in 0..3 {
+                match self[(x, y)] {
+                    Some(color) => write!(f, "{}", color)?,
+                    None => write!(f, ".")?,
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        connect4::Color,
+        game::{
+            alpha_beta, minimax,
+            search::{Algorithm, Searcher},
+            transposition::TranspositionTable,
+            MinimaxGame,
+        },
+    };
+
+    use super::Game;
+
+    fn play(spaces: &[usize]) -> Game {
+        spaces
+            .iter()
+            .fold(Game::new(), |game, space| game.add_piece(*space))
+    }
+
+    /// Counts the different ways a game can be played to the end
+    fn count_games(game: &Game) -> u64 {
+        if game.has_finished() {
+            return 1;
+        }
+
+        game.legal_moves()
+            .map(|space| count_games(&game.apply(space)))
+            .sum()
+    }
+
+    #[test]
+    pub fn counts_every_game() {
+        assert_eq!(count_games(&Game::new()), 255_168);
+    }
+
+    #[test]
+    pub fn perfect_play_draws() {
+        for algorithm in [
+            Algorithm::AlphaBeta,
+            Algorithm::FailSoft,
+            Algorithm::Negascout,
+            Algorithm::MtdF,
+        ] {
+            let mut table = TranspositionTable::new(1 << 12);
+            let result = Searcher::new(algorithm)
+                .with_table(&mut table)
+                .search(&Game::new(), 9);
+
+            assert_eq!(result.score, 0, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    pub fn finds_wins_and_blocks() {
+        // x x .
+        // o o .
+        // . . .
+        let game = play(&[0, 3, 1, 4]);
+        assert_eq!(minimax(&game, 1), 2);
+        assert!(alpha_beta(&game, 9).score > 0);
+
+        // o has to block the top row
+        let game = play(&[0, 3, 1]);
+        assert_eq!(alpha_beta(&game, 9).best_move, 2);
+
+        // Taking a corner against a centre opening still draws, but an edge loses
+        let game = play(&[4]);
+        assert_eq!(alpha_beta(&game.add_piece(0), 9).score, 0);
+        assert!(alpha_beta(&game.add_piece(1), 9).score > 0);
+    }
+
+    #[test]
+    pub fn winner() {
+        let game = play(&[0, 3, 1, 4, 2]);
+
+        assert!(game.has_won());
+        assert_eq!(game.winner(), Some(Color::White));
+        assert_eq!(game.legal_moves().count(), 0);
+        assert_eq!(format!("{}", game), "xxx\noo.\n...\n");
+
+        let draw = play(&[4, 0, 2, 6, 3, 5, 1, 7, 8]);
+        assert!(draw.has_tied());
+        assert_eq!(draw.winner(), None);
+    }
+}